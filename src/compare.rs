@@ -0,0 +1,257 @@
+// Side by side summary of one workout for several athletes (pace tables):
+//
+//                   anna      ben
+//     distance      11.9 km   11.8 km
+//     duration      1:09 h    1:12 h
+//     pace          5:45      6:05
+//     10 E          5:40      6:00
+//     3 * (
+//       2 min I     3:55      4:10
+//       2 min rst   15:00     15:00
+//     )
+//
+// The group analysis shows what each athlete runs per rep: the distance of time based
+// steps and the time of distance based steps. For distance based steps it also shows
+// how far behind everyone is when the fastest athlete finishes (400 m: lapped on a
+// track) and rep distances that take everyone as long as the slowest athlete needs:
+//
+//                      anna     ben      spread
+//     3 * (
+//       1.6 I          6:16     6:40     0:24
+//         behind       0 m      96 m
+//         equal time   1702 m   1600 m
+//       2 min rst      133 m    133 m    0 m
+//     )
+//     4 * (
+//       2 min I        511 m    480 m    31 m
+//       1 min rst      67 m     67 m     0 m
+//     )
+
+use crate::config::{self, Config};
+use crate::format::format_part;
+use crate::parse::{self, ParseError};
+use crate::utils::{Duration, Speed};
+use crate::wtree::{RunPart, RunType};
+
+pub(crate) type Rows = Vec<(String, Vec<String>)>;
+// rows of a step: indent, canonical step text and the step of every athlete
+pub(crate) type StepRows<'a> = &'a dyn Fn(&str, String, &[StepInfo], &mut Rows);
+
+/// Compare `workout` for all `athletes`, parsed with the config of each athlete.
+pub fn compare(workout: &str, athletes: &[(String, Config)]) -> Result<String, ParseError> {
+    let parsed = parse_all(workout, athletes)?;
+    let mut rows = header(athletes, &[]);
+    let units = config::get_units();
+    rows.push(total_row("distance", &parsed, |w| {
+        format!("{} {}", units.distance(w.calc_distance()), units.label())
+    }));
+    rows.push(total_row("duration", &parsed, |w| {
+        format!("{} h", Duration(w.calc_time()).hours_minutes())
+    }));
+    rows.push(total_row("pace", &parsed, |w| {
+        units.pace(Speed(
+            w.calc_distance() / (w.calc_time() - w.calc_hr_time()),
+        ))
+    }));
+    walk(&parsed, &mut rows, &|indent, label, steps, rows| {
+        rows.push((
+            indent.to_string() + &label,
+            steps.iter().map(|s| s.pace.clone()).collect(),
+        ));
+    });
+    Ok(render(&rows))
+}
+
+/// Group session analysis of `workout` for all `athletes`: per rep distances or times,
+/// the spread between fastest and slowest and rep distances that equalise durations.
+pub fn group(workout: &str, athletes: &[(String, Config)]) -> Result<String, ParseError> {
+    let parsed = parse_all(workout, athletes)?;
+    let mut rows = header(athletes, &["spread"]);
+    walk(&parsed, &mut rows, &|indent, label, steps, rows| {
+        let label = indent.to_string() + &label;
+        let fastest = steps.iter().map(|s| s.speed.0).fold(f32::MIN, f32::max);
+        let slowest = steps.iter().map(|s| s.speed.0).fold(f32::MAX, f32::min);
+        match steps[0].rtype {
+            RunType::Time => {
+                let mut cells: Vec<String> = steps.iter().map(|s| meters(s.distance)).collect();
+                cells.push(meters(steps[0].time.0 * (fastest - slowest)));
+                rows.push((label, cells));
+            }
+            RunType::Distance => {
+                let distance = steps[0].distance;
+                let mut cells: Vec<String> = steps.iter().map(|s| s.time.to_string()).collect();
+                cells.push(minutes(distance / slowest - distance / fastest));
+                rows.push((label, cells));
+                // position of everyone when the fastest athlete finishes the rep
+                rows.push((
+                    format!("{}  behind", indent),
+                    steps
+                        .iter()
+                        .map(|s| meters(distance - distance * s.speed.0 / fastest))
+                        .collect(),
+                ));
+                rows.push((
+                    format!("{}  equal time", indent),
+                    steps
+                        .iter()
+                        .map(|s| meters(distance * s.speed.0 / slowest))
+                        .collect(),
+                ));
+            }
+        }
+    });
+    Ok(render(&rows))
+}
+
+// a step of one athlete's workout
+pub(crate) struct StepInfo {
+    pub rtype: RunType,
+    pub pace: String,
+    pub speed: Speed,                 // average speed of progressions
+    pub ramp: Option<(Speed, Speed)>, // start and end speed of progressions
+    pub time: Duration,
+    pub distance: f32,
+}
+
+fn parse_all(workout: &str, athletes: &[(String, Config)]) -> Result<Vec<RunPart>, ParseError> {
+    let mut parsed = Vec::new();
+    for (_, athlete) in athletes.iter() {
+        config::init(athlete.resolved_paces());
+        config::init_aliases(athlete.aliases.clone().into_iter().collect());
+        config::init_units(athlete.units);
+        parsed.push(parse::parse(workout)?);
+    }
+    Ok(parsed)
+}
+
+fn header(athletes: &[(String, Config)], extra: &[&str]) -> Rows {
+    let mut names: Vec<String> = athletes.iter().map(|(name, _)| name.clone()).collect();
+    names.extend(extra.iter().map(|e| e.to_string()));
+    vec![(String::new(), names)]
+}
+
+fn meters(distance: f32) -> String {
+    // + 0.0 avoids "-0 m"
+    format!("{:.0} m", distance.round() + 0.0)
+}
+
+fn minutes(time: f32) -> String {
+    Duration(time).to_string()
+}
+
+// align all columns
+pub(crate) fn render(rows: &Rows) -> String {
+    let columns = rows[0].1.len() + 1;
+    let mut widths = vec![0; columns];
+    for (label, cells) in rows.iter() {
+        widths[0] = widths[0].max(label.chars().count());
+        for (i, cell) in cells.iter().enumerate() {
+            widths[i + 1] = widths[i + 1].max(cell.chars().count());
+        }
+    }
+    let mut out = String::new();
+    for (label, cells) in rows.iter() {
+        let mut line = format!("{:w$}", label, w = widths[0]);
+        for (i, cell) in cells.iter().enumerate() {
+            line.push_str(&format!("   {:w$}", cell, w = widths[i + 1]));
+        }
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+fn total_row(
+    label: &str,
+    parsed: &[RunPart],
+    f: impl Fn(&RunPart) -> String,
+) -> (String, Vec<String>) {
+    (label.to_string(), parsed.iter().map(f).collect())
+}
+
+// the same node of every athlete's workout
+fn child<'a>(parts: &[&'a RunPart], i: usize) -> Vec<&'a RunPart> {
+    parts
+        .iter()
+        .map(|p| match p {
+            RunPart::Workout { nodes, .. } => &nodes[i],
+            RunPart::Step { .. } => *p,
+        })
+        .collect()
+}
+
+// rows of all steps, labelled with the canonical step text. Repeats are shown once.
+pub(crate) fn walk(parsed: &[RunPart], rows: &mut Rows, step: StepRows) {
+    let parsed: Vec<&RunPart> = parsed.iter().collect();
+    if let RunPart::Workout { nodes, .. } = parsed[0] {
+        for i in 0..nodes.len() {
+            walk_part(&child(&parsed, i), "", rows, step);
+        }
+    }
+}
+
+fn walk_part(parts: &[&RunPart], indent: &str, rows: &mut Rows, step: StepRows) {
+    match parts[0] {
+        RunPart::Step { .. } => {
+            step(indent, format_part(parts[0]), &step_infos(parts), rows);
+        }
+        RunPart::Workout {
+            reps,
+            nodes,
+            recovery,
+            ladder,
+        } => {
+            let open = match ladder {
+                true => "ladder(".to_string(),
+                false => format!("{} * (", reps),
+            };
+            rows.push((format!("{}{}", indent, open), Vec::new()));
+            for i in 0..nodes.len() {
+                walk_part(&child(parts, i), &format!("{}  ", indent), rows, step);
+            }
+            if let Some(r) = recovery {
+                let recoveries: Vec<&RunPart> = parts
+                    .iter()
+                    .filter_map(|p| match p {
+                        RunPart::Workout {
+                            recovery: Some(r), ..
+                        } => Some(r.as_ref()),
+                        _ => None,
+                    })
+                    .collect();
+                let label = format!("w/ {}", format_part(r));
+                step(
+                    &format!("{}  ", indent),
+                    label,
+                    &step_infos(&recoveries),
+                    rows,
+                );
+            }
+            rows.push((format!("{})", indent), Vec::new()));
+        }
+    }
+}
+
+fn step_infos(parts: &[&RunPart]) -> Vec<StepInfo> {
+    parts
+        .iter()
+        .filter_map(|p| match p {
+            RunPart::Step {
+                rtype,
+                speed,
+                end_speed,
+                time,
+                distance,
+                ..
+            } => Some(StepInfo {
+                rtype: rtype.clone(),
+                pace: p.pace(),
+                speed: end_speed.map_or(*speed, |end| Speed((speed.0 + end.0) / 2.0)),
+                ramp: end_speed.map(|end| (*speed, end)),
+                time: *time,
+                distance: *distance,
+            }),
+            RunPart::Workout { .. } => None,
+        })
+        .collect()
+}
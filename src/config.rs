@@ -1,28 +1,183 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Mutex;
 
 use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::{format_hr, hr_range, pace_range, split_target, Units};
+
+/// Content of a `paces.toml` file, shared between CLI and GUI.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Config {
+    // metric or imperial distances and paces
+    #[serde(default, skip_serializing_if = "Units::is_metric")]
+    pub units: Units,
+    // maximum and lactate threshold heart rate (bpm) for heart rate targets in percent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_hr: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lthr: Option<u32>,
+    // paces, optionally with a heart rate, e.g. `E = "5:30 @ 130-145 bpm"`, or heart
+    // rates only, e.g. `Z2 = "65-75% max"`
+    #[serde(default)]
+    pub paces: BTreeMap<String, String>,
+    // alternative names of intensities, e.g. `jog = "jg"`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub aliases: BTreeMap<String, String>,
+    // athlete specific paces, `[athletes.<name>.paces]` tables
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub athletes: BTreeMap<String, Athlete>,
+}
+
+/// Paces of a single athlete in a shared config file.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Athlete {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_hr: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lthr: Option<u32>,
+    #[serde(default)]
+    pub paces: BTreeMap<String, String>,
+}
+
+impl Config {
+    /// Config of a single athlete: the common paces extended or overridden by the
+    /// athlete's own paces.
+    pub fn athlete(&self, name: &str) -> Result<Config, String> {
+        let athlete = self
+            .athletes
+            .get(name)
+            .ok_or(format!("unknown athlete {}", name))?;
+        let mut paces = self.paces.clone();
+        paces.extend(athlete.paces.clone());
+        Ok(Config {
+            units: self.units,
+            max_hr: athlete.max_hr.or(self.max_hr),
+            lthr: athlete.lthr.or(self.lthr),
+            paces,
+            aliases: self.aliases.clone(),
+            athletes: BTreeMap::new(),
+        })
+    }
+
+    /// Check that all paces (or pace ranges) and heart rates are valid and all aliases
+    /// refer to an intensity.
+    pub fn check(&self) -> Result<(), String> {
+        for (k, v) in self.paces.iter() {
+            let (pace, hr) = split_target(v);
+            if let Some(pace) = pace {
+                pace_range(pace, self.units)
+                    .ok_or(format!("invalid pace \"{}\" for {}", pace, k))?;
+            }
+            if let Some(hr) = hr {
+                hr_range(hr, self.max_hr, self.lthr)
+                    .ok_or(format!("invalid heart rate \"{}\" for {}", hr, k))?;
+            }
+        }
+        for (alias, intensity) in self.aliases.iter() {
+            if self.paces.contains_key(alias) {
+                return Err(format!("alias {} is also defined as intensity", alias));
+            }
+            if !self.paces.contains_key(intensity) {
+                return Err(format!(
+                    "alias {} refers to unknown intensity {}",
+                    alias, intensity
+                ));
+            }
+        }
+        for name in self.athletes.keys() {
+            self.athlete(name)?
+                .check()
+                .map_err(|e| format!("athlete {}: {}", name, e))?;
+        }
+        Ok(())
+    }
+
+    /// Paces with heart rates in bpm, percentages of the maximum or threshold heart rate
+    /// are converted. Invalid heart rates are kept, see `check`.
+    pub fn resolved_paces(&self) -> HashMap<String, String> {
+        let resolve = |v: &String| {
+            let (pace, hr) = split_target(v);
+            match (pace, hr.and_then(|hr| hr_range(hr, self.max_hr, self.lthr))) {
+                (Some(pace), Some(hr)) => format!("{} @ {}", pace, format_hr(hr)),
+                (None, Some(hr)) => format_hr(hr),
+                _ => v.clone(),
+            }
+        };
+        self.paces
+            .iter()
+            .map(|(k, v)| (k.clone(), resolve(v)))
+            .collect()
+    }
+}
 
 static CONFIG: Lazy<Mutex<HashMap<String, String>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+static ALIASES: Lazy<Mutex<HashMap<String, String>>> = Lazy::new(|| Mutex::new(HashMap::new()));
 
 pub fn init(paces: HashMap<String, String>) {
     let mut config = CONFIG.lock().unwrap();
     *config = paces;
 }
 
+pub fn init_aliases(aliases: HashMap<String, String>) {
+    let mut config = ALIASES.lock().unwrap();
+    *config = aliases;
+}
+
+// the unit system is per thread in unit tests, they run in parallel
 #[cfg(not(test))]
-pub fn get_pace(effort: &str) -> String {
-    CONFIG.lock().unwrap()[effort].clone()
+static UNITS: Mutex<Units> = Mutex::new(Units::Metric);
+#[cfg(test)]
+thread_local! {
+    static UNITS: std::cell::Cell<Units> = const { std::cell::Cell::new(Units::Metric) };
 }
 
+pub fn init_units(units: Units) {
+    #[cfg(not(test))]
+    {
+        *UNITS.lock().unwrap() = units;
+    }
+    #[cfg(test)]
+    UNITS.set(units);
+}
+
+pub fn get_units() -> Units {
+    #[cfg(not(test))]
+    return *UNITS.lock().unwrap();
+    #[cfg(test)]
+    UNITS.get()
+}
+
+/// Pace (and heart rate) of an intensity or alias, `None` for unknown intensities.
+#[cfg(not(test))]
+pub fn get_pace(effort: &str) -> Option<String> {
+    CONFIG
+        .lock()
+        .unwrap()
+        .get(&canonical_intensity(effort))
+        .cloned()
+}
+
+/// Name of the intensity an alias refers to, other names are returned unchanged.
+#[cfg(not(test))]
+pub fn canonical_intensity(effort: &str) -> String {
+    match ALIASES.lock().unwrap().get(effort) {
+        Some(intensity) => intensity.clone(),
+        None => effort.to_string(),
+    }
+}
+
+// intensities including aliases
 #[cfg(not(test))]
 pub fn get_intensities() -> Vec<String> {
-    CONFIG.lock().unwrap().keys().cloned().collect()
+    let mut intensities: Vec<String> = CONFIG.lock().unwrap().keys().cloned().collect();
+    intensities.extend(ALIASES.lock().unwrap().keys().cloned());
+    intensities
 }
 
 // unit tests use a hard-coded config
 #[cfg(test)]
-pub fn get_pace(effort: &str) -> &str {
+pub fn get_pace(effort: &str) -> Option<String> {
     let inline_config: HashMap<&str, &str> = HashMap::from([
         ("E", "6:00"),
         ("M", "5:00"),
@@ -30,17 +185,28 @@ pub fn get_pace(effort: &str) -> &str {
         ("I", "4:00"),
         ("H", "4:00"),
         ("R", "3:30"),
+        ("LT", "4:10-4:20"),
         ("jg", "8:00"),
-        ("jog", "8:00"),
         ("rst", "15:00"),
-        ("rest", "15:00"),
+        ("LR", "6:15 @ 140-150 bpm"),
+        ("Z2", "130-145 bpm"),
     ]);
-    inline_config[effort]
+    inline_config
+        .get(canonical_intensity(effort).as_str())
+        .map(|pace| pace.to_string())
+}
+
+#[cfg(test)]
+pub fn canonical_intensity(effort: &str) -> String {
+    let inline_aliases: HashMap<&str, &str> = HashMap::from([("jog", "jg"), ("rest", "rst")]);
+    inline_aliases.get(effort).unwrap_or(&effort).to_string()
 }
 
 #[cfg(test)]
 pub fn get_intensities() -> Vec<String> {
-    let intensities = ["E", "M", "T", "I", "H", "R", "jg", "jog", "rst", "rest"];
+    let intensities = [
+        "E", "M", "T", "I", "H", "R", "LT", "jg", "jog", "rst", "rest", "LR", "Z2",
+    ];
     intensities.iter().map(|s| s.to_string()).collect()
 }
 
@@ -50,6 +216,107 @@ mod tests {
 
     #[test]
     fn test_get_pace() {
-        assert_eq!("4:00", get_pace("H"));
+        assert_eq!(get_pace("H").unwrap(), "4:00");
+        assert_eq!(get_pace("rest").unwrap(), "15:00");
+        assert_eq!(get_pace("X"), None);
+    }
+
+    #[test]
+    fn check_aliases() {
+        let mut config: Config = toml::from_str(
+            r#"
+            [paces]
+            E = "5:30"
+            T = "4:10-4:20"
+            jg = "6:30"
+            [aliases]
+            jog = "jg"
+            "#,
+        )
+        .unwrap();
+        assert!(config.check().is_ok());
+        config.paces.insert("M".to_string(), "4:50-".to_string());
+        assert!(config.check().is_err());
+        config.paces.remove("M");
+        config.aliases.insert("run".to_string(), "R".to_string());
+        assert!(config.check().is_err());
+        config.aliases.clear();
+        config.aliases.insert("E".to_string(), "jg".to_string());
+        assert!(config.check().is_err());
+    }
+
+    #[test]
+    fn imperial_units() {
+        let config: Config = toml::from_str(
+            r#"
+            units = "imperial"
+            [paces]
+            E = "9:00"
+            [athletes.anna.paces]
+            E = "8:30"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.units, Units::Imperial);
+        assert_eq!(config.athlete("anna").unwrap().units, Units::Imperial);
+        assert!(config.check().is_ok());
+        let metric: Config = toml::from_str("[paces]\nE = \"5:30\"").unwrap();
+        assert_eq!(metric.units, Units::Metric);
+        assert!(!toml::to_string(&metric).unwrap().contains("units"));
+    }
+
+    #[test]
+    fn heart_rates() {
+        let config: Config = toml::from_str(
+            r#"
+            max_hr = 190
+            [paces]
+            E = "5:30 @ 65-75% max"
+            Z2 = "130-145 bpm"
+            T = "4:15"
+            [athletes.anna]
+            lthr = 170
+            [athletes.anna.paces]
+            Z3 = "85-89% lthr"
+            "#,
+        )
+        .unwrap();
+        assert!(config.check().is_ok());
+        let paces = config.resolved_paces();
+        assert_eq!(paces["E"], "5:30 @ 124-143 bpm");
+        assert_eq!(paces["Z2"], "130-145 bpm");
+        assert_eq!(paces["T"], "4:15");
+        let anna = config.athlete("anna").unwrap();
+        assert_eq!(anna.max_hr, Some(190));
+        assert_eq!(anna.resolved_paces()["Z3"], "145-151 bpm");
+
+        // percentages need the heart rate they refer to
+        let config: Config = toml::from_str("[paces]\nZ3 = \"85-89% lthr\"").unwrap();
+        assert!(config.check().is_err());
+        let config: Config = toml::from_str("[paces]\nE = \"5:30 @ fast\"").unwrap();
+        assert!(config.check().is_err());
+    }
+
+    #[test]
+    fn athlete_paces() {
+        let config: Config = toml::from_str(
+            r#"
+            [paces]
+            E = "5:30"
+            rst = "15:00"
+            [athletes.anna.paces]
+            E = "5:00"
+            T = "4:00"
+            [athletes.ben.paces]
+            E = "6:00"
+            "#,
+        )
+        .unwrap();
+        let anna = config.athlete("anna").unwrap();
+        assert_eq!(anna.paces.len(), 3);
+        assert_eq!(anna.paces["E"], "5:00");
+        assert_eq!(anna.paces["rst"], "15:00");
+        assert_eq!(config.athlete("ben").unwrap().paces["E"], "6:00");
+        assert!(config.athlete("carl").is_err());
     }
 }
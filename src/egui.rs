@@ -1,95 +1,765 @@
-use crate::config;
+use crate::config::{self, Config};
+use crate::macros;
 use crate::parse;
+use crate::plan;
+use crate::share;
+use crate::track::{track, Track};
+use crate::treadmill::{treadmill, Treadmill};
 use crate::utils::*;
 
 use eframe::egui;
-use std::collections::HashMap;
+use eframe::egui::text::{LayoutJob, TextFormat};
+use eframe::egui::{Color32, Stroke};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
 
+/// Start the GUI. `config` and `workouts` (one workout per line) replace the state of the
+/// previous session if given.
 #[cfg(not(target_arch = "wasm32"))]
-pub fn gui_create() -> eframe::Result<()> {
+pub fn gui_create(config: Option<Config>, workouts: Option<String>) -> eframe::Result<()> {
     let native_options = eframe::NativeOptions::default();
     eframe::run_native(
         "Running Workout",
         native_options,
-        Box::new(|cc| Ok(Box::new(WorkoutApp::new(cc)))),
+        Box::new(|cc| Ok(Box::new(WorkoutApp::new(cc, config, workouts)))),
     )
 }
 
 #[cfg(target_arch = "wasm32")]
-pub fn gui_create() {
+pub fn gui_create(config: Option<Config>, workouts: Option<String>) {
     // Redirect `log` message to `console.log` and friends:
     eframe::WebLogger::init(log::LevelFilter::Debug).ok();
-    let web_options = eframe::WebOptions {
-        max_size_points: egui::Vec2 { x: 500.0, y: 500.0 },
-        ..Default::default()
-    };
+    // the canvas size is defined by the CSS in index.html
+    let web_options = eframe::WebOptions::default();
 
     wasm_bindgen_futures::spawn_local(async {
         eframe::WebRunner::new()
             .start(
                 "egui_canvas_id", // hardcode it
                 web_options,
-                Box::new(|cc| Box::new(WorkoutApp::new(cc))),
+                Box::new(|cc| Ok(Box::new(WorkoutApp::new(cc, config, workouts)))),
             )
             .await
             .expect("failed to start eframe");
     });
 }
 
-#[derive(Default)]
+// intensities and workouts are persisted between sessions, see `eframe::App::save`
+#[derive(Deserialize, Serialize)]
+#[serde(default)]
 struct WorkoutApp {
-    config: HashMap<String, f32>,
+    config: HashMap<String, f32>, // speed, the high speed of pace ranges
+    ranges: HashMap<String, f32>, // low speed of intensities with a pace range
+    heart_rates: HashMap<String, (u32, u32)>, // bpm, heart rate targets of intensities
+    units: Units,
+    aliases: BTreeMap<String, String>,
+    imported: Config, // last imported config, exports keep its athletes and settings
     workout: String,
+    view: View,
+    per_week: usize, // workouts per week if the plan has no empty lines
+    treadmill: Treadmill,
+    track: Track,
+    #[cfg(not(target_arch = "wasm32"))]
+    share_url: String, // web GUI address used for share links, e.g. http://localhost:8000/
+    #[serde(skip)]
     tmp: Tmp, // used for intermediate, temporary gui data
 }
 
+#[derive(Clone, Copy, PartialEq, Deserialize, Serialize)]
+enum View {
+    Workouts,
+    Plan,
+    Treadmill,
+    Track,
+}
+
 #[derive(Default)]
 struct Tmp {
     new_pace: String,
     new_intensity: String,
     remove_config: String,
+    remove_alias: String,
+    #[cfg(not(target_arch = "wasm32"))]
+    paces_file: String, // path for paces.toml import/export
+    #[cfg(target_arch = "wasm32")]
+    paces_toml: String, // paces.toml import/export buffer
+    paces_status: String, // result of the last import/export
+    #[cfg(not(target_arch = "wasm32"))]
+    plan_file: String,
+    #[cfg(not(target_arch = "wasm32"))]
+    plan_status: String, // result of the last plan load/save
+    show_intensities: Option<bool>, // None: depending on the screen width
 }
 
 impl WorkoutApp {
-    fn new(_cc: &eframe::CreationContext<'_>) -> Self {
+    fn new(
+        cc: &eframe::CreationContext<'_>,
+        config: Option<Config>,
+        workouts: Option<String>,
+    ) -> Self {
+        // restore the previous session (file on native, local storage on the web)
+        let mut app: WorkoutApp = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, eframe::APP_KEY))
+            .unwrap_or_default();
+        if let Some(config) = config {
+            if let Err(e) = app.set_config(config) {
+                app.tmp.paces_status = e;
+            }
+        }
+        if let Some(workouts) = workouts {
+            app.workout = workouts;
+        }
+        app
+    }
+}
+
+impl Default for WorkoutApp {
+    fn default() -> Self {
         Self {
             config: HashMap::from([
-                ("rst".to_owned(), pace2speed("10:00").unwrap()),
-                ("E".to_owned(), pace2speed("5:30").unwrap()),
-                ("M".to_owned(), pace2speed("4:50").unwrap()),
-                ("T".to_owned(), pace2speed("4:25").unwrap()),
-                ("I".to_owned(), pace2speed("4:00").unwrap()),
-                ("R".to_owned(), pace2speed("3:45").unwrap()),
+                ("rst".to_owned(), "10:00".parse::<Speed>().unwrap().0),
+                ("E".to_owned(), "5:30".parse::<Speed>().unwrap().0),
+                ("M".to_owned(), "4:50".parse::<Speed>().unwrap().0),
+                ("T".to_owned(), "4:25".parse::<Speed>().unwrap().0),
+                ("I".to_owned(), "4:00".parse::<Speed>().unwrap().0),
+                ("R".to_owned(), "3:45".parse::<Speed>().unwrap().0),
             ]),
+            ranges: HashMap::new(),
+            heart_rates: HashMap::new(),
+            units: Units::Metric,
+            aliases: BTreeMap::new(),
+            imported: Config::default(),
             workout: "2E + 8 * (400 R + 90s rst) + 10 min E".to_owned(),
+            view: View::Workouts,
+            per_week: 3,
+            treadmill: Treadmill::default(),
+            track: Track::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            share_url: String::new(), // no sharing until the address is set
             tmp: Tmp::default(),
         }
     }
 }
 
-fn paces_to_strings(input: &HashMap<String, f32>) -> HashMap<String, String> {
+impl WorkoutApp {
+    fn import_paces(&mut self, input: &str) -> Result<(), String> {
+        let config: Config = toml::from_str(input).map_err(|e| e.to_string())?;
+        self.set_config(config)
+    }
+
+    fn set_config(&mut self, config: Config) -> Result<(), String> {
+        config.check()?;
+        self.imported = config.clone();
+        self.units = config.units;
+        self.config.clear();
+        self.ranges.clear();
+        self.heart_rates.clear();
+        for (k, v) in config.paces.iter() {
+            self.insert_pace(k, v);
+        }
+        self.aliases = config.aliases;
+        Ok(())
+    }
+
+    // add or replace an intensity, returns false for invalid paces or heart rates. The
+    // pace may have a heart rate, e.g. "5:30 @ 130-145 bpm", or be a heart rate only
+    fn insert_pace(&mut self, intensity: &str, pace: &str) -> bool {
+        let (pace, hr) = split_target(pace);
+        let hr = match hr.map(|hr| hr_range(hr, self.imported.max_hr, self.imported.lthr)) {
+            Some(None) => return false,
+            Some(hr) => hr,
+            None => None,
+        };
+        let range = match pace.map(|pace| pace_range(pace, self.units)) {
+            Some(None) => return false,
+            Some(range) => range,
+            None if hr.is_some() => None,
+            None => return false,
+        };
+        match hr {
+            Some(hr) => self.heart_rates.insert(intensity.to_owned(), hr),
+            None => self.heart_rates.remove(intensity),
+        };
+        // heart rate only intensities have no slider
+        let Some((low, high)) = range else {
+            self.config.remove(intensity);
+            self.ranges.remove(intensity);
+            return true;
+        };
+        self.config.insert(intensity.to_owned(), high.0);
+        if low < high {
+            self.ranges.insert(intensity.to_owned(), low.0);
+        } else {
+            self.ranges.remove(intensity);
+        }
+        true
+    }
+
+    // paces of the intensities, unchanged imported paces keep their original text
+    fn current_paces(&self) -> BTreeMap<String, String> {
+        let normalize = |pace: &str| {
+            let (pace, hr) = split_target(pace);
+            let pace = pace
+                .and_then(|p| pace_range(p, self.units))
+                .map(|(low, high)| range2pace(low, high, self.units));
+            let hr = hr.and_then(|hr| hr_range(hr, self.imported.max_hr, self.imported.lthr));
+            (pace, hr)
+        };
+        paces_to_strings(&self.config, &self.ranges, &self.heart_rates, self.units)
+            .into_iter()
+            .map(|(k, v)| match self.imported.paces.get(&k) {
+                Some(original) if normalize(original) == normalize(&v) => (k, original.clone()),
+                _ => (k, v),
+            })
+            .collect()
+    }
+
+    // the imported config with the paces and aliases of the GUI
+    fn current_config(&self) -> Config {
+        Config {
+            units: self.units,
+            paces: self.current_paces(),
+            aliases: self.aliases.clone(),
+            ..self.imported.clone()
+        }
+    }
+
+    fn export_paces(&self) -> String {
+        toml::to_string(&self.current_config()).unwrap()
+    }
+
+    // link to the web GUI, opening it with the current workouts, paces and aliases
+    fn share_link(&self) -> String {
+        let paces = paces_to_strings(&self.config, &self.ranges, &self.heart_rates, self.units)
+            .into_iter()
+            .collect();
+        let fragment = share::encode_fragment(&self.workout, &paces, &self.aliases, self.units);
+
+        #[cfg(not(target_arch = "wasm32"))]
+        let base = self.share_url.trim().to_string();
+        #[cfg(target_arch = "wasm32")]
+        let base = web_sys::window()
+            .and_then(|w| {
+                let location = w.location();
+                Some(location.origin().ok()? + &location.pathname().ok()?)
+            })
+            .unwrap_or_default();
+
+        format!("{}#{}", base, fragment)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn paces_file(&self) -> &str {
+        match self.tmp.paces_file.as_str() {
+            "" => "paces.toml",
+            f => f,
+        }
+    }
+}
+
+// paces per km or mile of `units`, with heart rates if any, and heart rate only
+// intensities
+fn paces_to_strings(
+    input: &HashMap<String, f32>,
+    ranges: &HashMap<String, f32>,
+    heart_rates: &HashMap<String, (u32, u32)>,
+    units: Units,
+) -> HashMap<String, String> {
     let mut out = HashMap::new();
     for (k, v) in input.iter() {
-        out.insert(k.clone(), speed2pace(*v));
+        let mut pace = match ranges.get(k) {
+            Some(low) => range2pace(Speed(low.min(*v)), Speed(low.max(*v)), units),
+            None => units.pace(Speed(*v)),
+        };
+        if let Some(hr) = heart_rates.get(k) {
+            pace = format!("{} @ {}", pace, format_hr(*hr));
+        }
+        out.insert(k.clone(), pace);
+    }
+    for (k, hr) in heart_rates.iter() {
+        if !input.contains_key(k) {
+            out.insert(k.clone(), format_hr(*hr));
+        }
     }
     out
 }
 
+// classes of workout text used for syntax highlighting
+#[derive(Clone, Copy, PartialEq)]
+enum Token {
+    Number,
+    Unit,
+    Intensity,
+    Keyword,
+    Other,
+}
+
+fn tokenize(line: &str, intensities: &[String]) -> Vec<(Range<usize>, Token)> {
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    let mut after_number = false;
+    while let Some(c) = line[pos..].chars().next() {
+        let rest = &line[pos..];
+        let unit = ["min", "km", "mi", "m", "s"]
+            .into_iter()
+            .find(|u| rest.starts_with(u));
+        let keyword = ["w/", "with", "ladder", "->", "~", "?"]
+            .into_iter()
+            .find(|k| rest.starts_with(k));
+        let intensity = intensities
+            .iter()
+            .filter(|i| rest.starts_with(i.as_str()))
+            .max_by_key(|i| i.len());
+        let (len, token) = if c.is_ascii_digit() || c == '.' {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+                .unwrap_or(rest.len());
+            (len, Token::Number)
+        } else if let (Some(u), true) = (unit, after_number) {
+            (u.len(), Token::Unit)
+        } else if c == 'x' && after_number {
+            // compact repetition, e.g. "8x400R"
+            (1, Token::Keyword)
+        } else if let Some(i) = intensity {
+            (i.len(), Token::Intensity)
+        } else if let Some(k) = keyword {
+            (k.len(), Token::Keyword)
+        } else {
+            (c.len_utf8(), Token::Other)
+        };
+        if !c.is_whitespace() {
+            after_number = token == Token::Number;
+        }
+        tokens.push((pos..pos + len, token));
+        pos += len;
+    }
+    tokens
+}
+
+// parse errors of all non-empty lines with spans relative to the complete text
+fn line_errors(text: &str) -> Vec<parse::ParseError> {
+    let mut errors = Vec::new();
+    let mut offset = 0;
+    let lines: Vec<&str> = text.split('\n').collect();
+    for (line, expanded) in lines
+        .iter()
+        .zip(macros::expand_lines(lines.iter().copied()))
+    {
+        let result = match expanded {
+            None => Ok(()),
+            Some(Ok(w)) if w.trim().is_empty() => Ok(()),
+            Some(Ok(w)) if w == *line => parse::parse(line).map(|_| ()),
+            // errors within expanded references can't be mapped back, underline the line
+            Some(Ok(w)) => parse::parse(&w).map(|_| ()).map_err(|mut e| {
+                let start = line.len() - line.trim_start().len();
+                e.span = start..line.trim_end().len();
+                e
+            }),
+            Some(Err(e)) => Err(e),
+        };
+        if let Err(mut e) = result {
+            // errors at the end of a line have an empty span, underline the last
+            // character instead
+            if e.span.is_empty() {
+                if let Some((i, c)) = line[..e.span.start].char_indices().last() {
+                    e.span = i..i + c.len_utf8();
+                }
+            }
+            e.span = offset + e.span.start..offset + e.span.end;
+            errors.push(e);
+        }
+        offset += line.len() + 1;
+    }
+    errors
+}
+
+fn highlight(ui: &egui::Ui, text: &str, intensities: &[String]) -> LayoutJob {
+    let font_id = egui::TextStyle::Monospace.resolve(ui.style());
+    let errors = line_errors(text);
+    let mut job = LayoutJob::default();
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        for (range, token) in tokenize(line, intensities) {
+            let color = match token {
+                Token::Number => Color32::from_rgb(0xd7, 0x99, 0x21),
+                Token::Unit => Color32::from_rgb(0x68, 0x9d, 0x6a),
+                Token::Intensity => Color32::from_rgb(0x45, 0x85, 0xc8),
+                Token::Keyword => Color32::from_rgb(0xb1, 0x62, 0x86),
+                Token::Other => ui.visuals().text_color(),
+            };
+            // split tokens at error boundaries to underline exactly the failing span
+            let (start, end) = (offset + range.start, offset + range.end);
+            let mut cuts = vec![start, end];
+            for e in errors.iter() {
+                cuts.extend(
+                    [e.span.start, e.span.end]
+                        .iter()
+                        .filter(|&&b| start < b && b < end),
+                );
+            }
+            cuts.sort();
+            for w in cuts.windows(2) {
+                let failed = errors
+                    .iter()
+                    .any(|e| e.span.start <= w[0] && w[1] <= e.span.end);
+                job.append(
+                    &text[w[0]..w[1]],
+                    0.0,
+                    TextFormat {
+                        font_id: font_id.clone(),
+                        color,
+                        underline: if failed {
+                            Stroke::new(1.5, ui.visuals().error_fg_color)
+                        } else {
+                            Stroke::NONE
+                        },
+                        ..Default::default()
+                    },
+                );
+            }
+        }
+        offset += line.len();
+    }
+    job
+}
+
+impl WorkoutApp {
+    fn workouts_view(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Workouts");
+        let intensities = config::get_intensities();
+        let mut layouter = |ui: &egui::Ui, text: &str, wrap_width: f32| {
+            let mut job = highlight(ui, text, &intensities);
+            job.wrap.max_width = wrap_width;
+            ui.fonts(|f| f.layout_job(job))
+        };
+        let output = egui::TextEdit::multiline(&mut self.workout)
+            .code_editor()
+            .desired_rows(8)
+            .desired_width(f32::INFINITY)
+            .layouter(&mut layouter)
+            .show(ui);
+
+        // explain the underlined error below the mouse pointer
+        if let Some(pos) = output.response.hover_pos() {
+            let cursor = output.galley.cursor_from_pos(pos - output.galley_pos);
+            let index = self
+                .workout
+                .char_indices()
+                .nth(cursor.ccursor.index)
+                .map_or(self.workout.len(), |(i, _)| i);
+            if let Some(e) = line_errors(&self.workout)
+                .into_iter()
+                .find(|e| e.span.start <= index && index <= e.span.end)
+            {
+                output.response.on_hover_text(e.message);
+            }
+        }
+
+        ui.heading("Summary");
+        egui::Grid::new("summary_grid")
+            .striped(true)
+            .show(ui, |ui| {
+                let lines = macros::expand_lines(self.workout.lines());
+                for (n, line) in lines.into_iter().enumerate() {
+                    // definitions have no summary
+                    let summary = match line {
+                        Some(Ok(w)) if w.trim().is_empty() => continue,
+                        Some(w) => w.and_then(|w| parse::try_summarize(&w)),
+                        None => continue,
+                    };
+                    ui.label(format!("{}", n + 1));
+                    match summary {
+                        Ok(s) => ui.label(s),
+                        Err(e) => ui.colored_label(ui.visuals().error_fg_color, e.message),
+                    };
+                    ui.end_row();
+                }
+            });
+    }
+
+    fn treadmill_view(&mut self, ui: &mut egui::Ui) {
+        let units = self.units;
+        ui.horizontal(|ui| {
+            ui.label("Increment:");
+            ui.add(
+                egui::DragValue::new(&mut self.treadmill.increment)
+                    .range(0.1..=1.0)
+                    .speed(0.05)
+                    .max_decimals(2)
+                    .suffix(if units.is_metric() { " km/h" } else { " mph" }),
+            );
+            ui.checkbox(&mut self.treadmill.incline, "1% incline")
+                .on_hover_text("makes up for the missing air resistance");
+        });
+        let lines = macros::expand_lines(self.workout.lines());
+        for (n, line) in lines.into_iter().enumerate() {
+            let table = match line {
+                Some(Ok(w)) if w.trim().is_empty() => continue,
+                Some(w) => w.and_then(|w| treadmill(&w, self.treadmill)),
+                None => continue,
+            };
+            ui.separator();
+            ui.label(format!("{}", n + 1));
+            match table {
+                Ok(t) => ui.monospace(t),
+                Err(e) => ui.colored_label(ui.visuals().error_fg_color, e.message),
+            };
+        }
+    }
+
+    fn track_view(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Track:");
+            ui.selectable_value(&mut self.track.length, 400.0, "400 m");
+            ui.selectable_value(&mut self.track.length, 200.0, "200 m");
+            ui.label("Lane:");
+            ui.add(egui::DragValue::new(&mut self.track.lane).range(1..=9));
+        });
+        let lines = macros::expand_lines(self.workout.lines());
+        for (n, line) in lines.into_iter().enumerate() {
+            let table = match line {
+                Some(Ok(w)) if w.trim().is_empty() => continue,
+                Some(w) => w.and_then(|w| track(&w, self.track)),
+                None => continue,
+            };
+            ui.separator();
+            ui.label(format!("{}", n + 1));
+            match table {
+                Ok(t) => ui.monospace(t),
+                Err(e) => ui.colored_label(ui.visuals().error_fg_color, e.message),
+            };
+        }
+    }
+
+    fn plan_view(&mut self, ui: &mut egui::Ui) {
+        #[cfg(not(target_arch = "wasm32"))]
+        ui.horizontal(|ui| {
+            ui.label("Plan file:");
+            ui.add(
+                egui::TextEdit::singleline(&mut self.tmp.plan_file)
+                    .hint_text("sample/2Q_plus.workouts"),
+            );
+            if ui.button("Load plan").clicked() {
+                match std::fs::read_to_string(&self.tmp.plan_file) {
+                    Ok(plan) => {
+                        self.workout = plan;
+                        self.tmp.plan_status = format!("loaded {}", self.tmp.plan_file);
+                    }
+                    Err(e) => self.tmp.plan_status = e.to_string(),
+                }
+            }
+            if ui.button("Save plan").clicked() {
+                self.tmp.plan_status = match std::fs::write(&self.tmp.plan_file, &self.workout) {
+                    Ok(()) => format!("saved {}", self.tmp.plan_file),
+                    Err(e) => e.to_string(),
+                };
+            }
+            ui.label(&self.tmp.plan_status);
+        });
+        ui.horizontal(|ui| {
+            ui.label("Workouts per week:");
+            ui.add(egui::DragValue::new(&mut self.per_week).range(1..=14));
+            ui.label("(only used if weeks are not separated by empty lines)");
+        });
+
+        // cells edit the lines of the workouts text directly
+        let mut lines: Vec<String> = self.workout.lines().map(str::to_owned).collect();
+        let weeks = plan::weeks(
+            &lines.iter().map(String::as_str).collect::<Vec<_>>(),
+            self.per_week,
+        );
+        let expanded = macros::expand_lines(lines.iter().map(String::as_str));
+        let columns = weeks.iter().map(Vec::len).max().unwrap_or(0);
+        let mut volumes = Vec::new();
+        let mut changed = false;
+
+        egui::ScrollArea::both()
+            .max_height(ui.available_height() - 180.0)
+            .show(ui, |ui| {
+                egui::Grid::new("plan_grid").striped(true).show(ui, |ui| {
+                    for (n, week) in weeks.iter().enumerate() {
+                        ui.label(format!("Week {}", n + 1));
+                        for &i in week {
+                            ui.vertical(|ui| {
+                                changed |= ui
+                                    .add(
+                                        egui::TextEdit::singleline(&mut lines[i])
+                                            .code_editor()
+                                            .desired_width(220.0),
+                                    )
+                                    .changed();
+                                let summary = match &expanded[i] {
+                                    Some(w) => w.clone().and_then(|w| parse::try_summarize(&w)),
+                                    // definitions are not part of any week
+                                    None => return,
+                                };
+                                match summary {
+                                    Ok(s) => ui.label(s),
+                                    Err(e) => {
+                                        ui.colored_label(ui.visuals().error_fg_color, e.message)
+                                    }
+                                };
+                            });
+                        }
+                        for _ in week.len()..columns {
+                            ui.label("");
+                        }
+                        let (distance, time) = plan::totals(week.iter().filter_map(|&i| {
+                            expanded[i].as_ref()?.as_ref().ok().map(String::as_str)
+                        }));
+                        ui.strong(format!(
+                            "{} {}, {} h",
+                            self.units.distance(distance),
+                            self.units.label(),
+                            Duration(time).hours_minutes()
+                        ));
+                        volumes.push(distance);
+                        ui.end_row();
+                    }
+                });
+            });
+
+        if changed {
+            let trailing_newline = self.workout.ends_with('\n');
+            self.workout = lines.join("\n");
+            if trailing_newline {
+                self.workout.push('\n');
+            }
+        }
+
+        ui.heading("Weekly volume");
+        volume_chart(ui, &volumes, self.units);
+    }
+}
+
+// bar chart of the weekly distances (m), labelled in km or miles
+fn volume_chart(ui: &mut egui::Ui, volumes: &[f32], units: Units) {
+    let (rect, response) = ui.allocate_exact_size(
+        egui::vec2(ui.available_width(), 140.0),
+        egui::Sense::hover(),
+    );
+    let max = volumes.iter().cloned().fold(0.0, f32::max);
+    if max <= 0.0 {
+        return;
+    }
+    let painter = ui.painter_at(rect);
+    let font_id = egui::TextStyle::Small.resolve(ui.style());
+    let label_height = 14.0;
+    let bar_width = rect.width() / volumes.len() as f32;
+    for (n, volume) in volumes.iter().enumerate() {
+        let left = rect.left() + n as f32 * bar_width;
+        let bottom = rect.bottom() - label_height;
+        let top = bottom - (rect.height() - 2.0 * label_height) * volume / max;
+        let bar = egui::Rect::from_min_max(
+            egui::pos2(left + 1.0, top),
+            egui::pos2(left + bar_width - 1.0, bottom),
+        );
+        painter.rect_filled(bar, 2.0, ui.visuals().selection.bg_fill);
+        painter.text(
+            egui::pos2(bar.center().x, rect.bottom()),
+            egui::Align2::CENTER_BOTTOM,
+            format!("{}", n + 1),
+            font_id.clone(),
+            ui.visuals().text_color(),
+        );
+        painter.text(
+            egui::pos2(bar.center().x, top),
+            egui::Align2::CENTER_BOTTOM,
+            format!("{:.0}", volume / units.length()),
+            font_id.clone(),
+            ui.visuals().text_color(),
+        );
+    }
+    if let Some(pos) = response.hover_pos() {
+        let n = ((pos.x - rect.left()) / bar_width) as usize;
+        if let Some(volume) = volumes.get(n) {
+            response.on_hover_text(format!(
+                "Week {}: {} {}",
+                n + 1,
+                units.distance(*volume),
+                units.label()
+            ));
+        }
+    }
+}
+
 impl eframe::App for WorkoutApp {
+    /// Called by the framework to save state before shutdown.
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, eframe::APP_KEY, self);
+    }
+
     /// Called each time the UI needs repainting, which may be many times per second.
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        // narrow screens (phones) start with collapsed instructions and intensities
+        let narrow = ctx.screen_rect().width() < 640.0;
+        let show_intensities = self.tmp.show_intensities.unwrap_or(!narrow);
+
         egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
-            ui.heading("Instructions");
-            ui.label("Workouts can be constructed from the defined intensities.");
-            ui.label("Distance numbers >= 100 are interpreted as meters, otherwise km.");
-            ui.label("Durations can be specified by adding 'min' or 's'");
-            ui.label("");
-            ui.label("Example: 2E + 8 * (400 R + 90s rst) + 10 min E");
+            egui::CollapsingHeader::new("Instructions")
+                .default_open(!narrow)
+                .show(ui, |ui| {
+                    ui.label("Workouts can be constructed from the defined intensities.");
+                    ui.label(format!(
+                        "Distance numbers >= 100 are interpreted as meters, otherwise {}.",
+                        self.units.label()
+                    ));
+                    ui.label("Distances can also be specified by adding 'km', 'mi' or 'm'");
+                    ui.label("Durations can be specified by adding 'min' or 's'");
+                    ui.label("'w/' adds a recovery between repetitions: 8 * 400 R w/ 90s jg");
+                    ui.label("Every line is summarized as a separate workout.");
+                    ui.label("");
+                    ui.label("Example: 2E + 8 * (400 R + 90s rst) + 10 min E");
+                    ui.label("Short form: 2E + 8x400R w/ 90s rst + 10 min E");
+                    ui.label("Ladder: ladder(400,800,1200,800,400) I w/ 2min jg");
+                    ui.label("Progression: 10km E->M");
+                    ui.label("Definitions: let WU = 3.2E, then WU + 8x400R + WU");
+                    ui.label("Fill up to a target: ? E + 5x1km I w/ 2min jg = 90min");
+                });
+            ui.horizontal_wrapped(|ui| {
+                let mut show = show_intensities;
+                if ui.toggle_value(&mut show, "Intensities").changed() {
+                    self.tmp.show_intensities = Some(show);
+                }
+                ui.separator();
+                ui.selectable_value(&mut self.view, View::Workouts, "Workouts");
+                ui.selectable_value(&mut self.view, View::Plan, "Plan");
+                ui.selectable_value(&mut self.view, View::Treadmill, "Treadmill");
+                ui.selectable_value(&mut self.view, View::Track, "Track");
+                ui.separator();
+                ui.selectable_value(&mut self.units, Units::Metric, "km")
+                    .on_hover_text("metric distances and paces");
+                ui.selectable_value(&mut self.units, Units::Imperial, "mi")
+                    .on_hover_text("imperial distances and paces");
+                ui.separator();
+                // native builds don't know where the web GUI is hosted
+                #[cfg(not(target_arch = "wasm32"))]
+                let enabled = !self.share_url.trim().is_empty();
+                #[cfg(target_arch = "wasm32")]
+                let enabled = true;
+                if ui
+                    .add_enabled(enabled, egui::Button::new("🔗 Copy share link"))
+                    .on_disabled_hover_text("enter the web GUI address to share workouts")
+                    .clicked()
+                {
+                    let link = self.share_link();
+                    ui.output_mut(|o| o.copied_text = link);
+                }
+                #[cfg(not(target_arch = "wasm32"))]
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.share_url)
+                        .desired_width(180.0)
+                        .hint_text("web GUI address"),
+                );
+            });
         });
 
-        egui::SidePanel::left("left_panel").show(ctx, |ui| {
-            ui.vertical(|ui| {
+        egui::SidePanel::left("left_panel").show_animated(ctx, show_intensities, |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| {
                 ui.heading("Intensities");
+                let units = self.units;
                 for (k, v) in self.config.iter_mut() {
                     ui.horizontal(|ui| {
                         if ui.button("🗙").clicked() {
@@ -98,11 +768,53 @@ impl eframe::App for WorkoutApp {
                         ui.add(
                             egui::Slider::new(v, 1.0..=8.0)
                                 .text(k)
-                                .custom_formatter(|n, _| speed2pace(n as f32))
-                                .custom_parser(|s| pace2speed(s).map(f64::from))
+                                .custom_formatter(|n, _| units.pace(Speed(n as f32)))
+                                .custom_parser(|s| {
+                                    Speed::parse(s, units.pace_unit()).ok().map(|s| s.0 as f64)
+                                })
                                 .trailing_fill(true),
                         );
+                        if let Some(hr) = self.heart_rates.get(k) {
+                            ui.label(format_hr(*hr));
+                        }
                     });
+                    // slow end of a pace range
+                    if let Some(low) = self.ranges.get_mut(k) {
+                        ui.horizontal(|ui| {
+                            ui.add_space(ui.spacing().interact_size.y);
+                            ui.add(
+                                egui::Slider::new(low, 1.0..=8.0)
+                                    .text(format!("{} (slow)", k))
+                                    .custom_formatter(|n, _| units.pace(Speed(n as f32)))
+                                    .custom_parser(|s| {
+                                        Speed::parse(s, units.pace_unit()).ok().map(|s| s.0 as f64)
+                                    })
+                                    .trailing_fill(true),
+                            );
+                        });
+                    }
+                }
+                for (k, hr) in self.heart_rates.iter() {
+                    if !self.config.contains_key(k) {
+                        ui.horizontal(|ui| {
+                            if ui.button("🗙").clicked() {
+                                self.tmp.remove_config = k.clone(); // schedule for removal
+                            }
+                            ui.label(format!("{}: {}", k, format_hr(*hr)));
+                        });
+                    }
+                }
+
+                if !self.aliases.is_empty() {
+                    ui.heading("Aliases");
+                    for (alias, intensity) in self.aliases.iter() {
+                        ui.horizontal(|ui| {
+                            if ui.button("🗙").clicked() {
+                                self.tmp.remove_alias = alias.clone(); // schedule for removal
+                            }
+                            ui.label(format!("{} → {}", alias, intensity));
+                        });
+                    }
                 }
 
                 ui.heading("Add new intensity");
@@ -112,40 +824,184 @@ impl eframe::App for WorkoutApp {
                     ui.text_edit_singleline(&mut self.tmp.new_intensity);
                 });
                 ui.horizontal(|ui| {
-                    ui.label("Pace (min:sec/km):");
+                    ui.label(format!(
+                        "Pace ({}, a range like 4:10-4:20, optionally @ 130-145 bpm, or a heart rate):",
+                        self.units.pace_unit().label()
+                    ));
                     ui.text_edit_singleline(&mut self.tmp.new_pace);
                 });
                 if ui.button("➕").clicked() {
-                    if let Some(pace) = pace2speed(&self.tmp.new_pace) {
-                        self.config.insert(self.tmp.new_intensity.clone(), pace);
+                    let (intensity, pace) =
+                        (self.tmp.new_intensity.clone(), self.tmp.new_pace.clone());
+                    if self.insert_pace(&intensity, &pace) {
+                        self.aliases.remove(&self.tmp.new_intensity);
                         self.tmp.new_pace = "".to_owned();
                         self.tmp.new_intensity = "".to_owned();
                     } else {
                         self.tmp.new_pace = "".to_owned();
                     }
                 }
+
+                ui.heading("paces.toml");
+
+                #[cfg(not(target_arch = "wasm32"))]
+                {
+                    ui.horizontal(|ui| {
+                        ui.label("File:");
+                        ui.add(
+                            egui::TextEdit::singleline(&mut self.tmp.paces_file)
+                                .hint_text("paces.toml"),
+                        );
+                    });
+                    ui.horizontal(|ui| {
+                        if ui.button("Import paces.toml").clicked() {
+                            let result = std::fs::read_to_string(self.paces_file())
+                                .map_err(|e| e.to_string())
+                                .and_then(|s| self.import_paces(&s));
+                            self.tmp.paces_status = match result {
+                                Ok(()) => format!("imported {}", self.paces_file()),
+                                Err(e) => e,
+                            };
+                        }
+                        if ui.button("Export paces.toml").clicked() {
+                            self.tmp.paces_status =
+                                match std::fs::write(self.paces_file(), self.export_paces()) {
+                                    Ok(()) => format!("exported {}", self.paces_file()),
+                                    Err(e) => e.to_string(),
+                                };
+                        }
+                    });
+                }
+
+                // no file system in the browser, import/export via copy & paste
+                #[cfg(target_arch = "wasm32")]
+                {
+                    ui.add(
+                        egui::TextEdit::multiline(&mut self.tmp.paces_toml)
+                            .code_editor()
+                            .desired_rows(4)
+                            .hint_text("paste paces.toml here"),
+                    );
+                    ui.horizontal(|ui| {
+                        if ui.button("Import paces.toml").clicked() {
+                            let input = self.tmp.paces_toml.clone();
+                            self.tmp.paces_status = match self.import_paces(&input) {
+                                Ok(()) => "imported".to_owned(),
+                                Err(e) => e,
+                            };
+                        }
+                        if ui.button("Export paces.toml").clicked() {
+                            self.tmp.paces_toml = self.export_paces();
+                            ui.output_mut(|o| o.copied_text = self.tmp.paces_toml.clone());
+                            self.tmp.paces_status = "copied to clipboard".to_owned();
+                        }
+                    });
+                }
+
+                ui.label(&self.tmp.paces_status);
             });
         });
 
         egui::CentralPanel::default().show(ctx, |ui| {
-            ui.horizontal(|ui| {
-                ui.vertical(|ui| {
-                    ui.heading("Workout");
-                    ui.text_edit_singleline(&mut self.workout);
-
-                    ui.heading("Summary");
-                    config::init(paces_to_strings(&self.config));
-                    ui.label(
-                        parse::summarize(&self.workout).unwrap_or("invalid workout".to_string()),
-                    );
-                });
-            });
+            config::init(paces_to_strings(
+                &self.config,
+                &self.ranges,
+                &self.heart_rates,
+                self.units,
+            ));
+            config::init_aliases(self.aliases.clone().into_iter().collect());
+            config::init_units(self.units);
+            match self.view {
+                View::Workouts => {
+                    egui::ScrollArea::vertical().show(ui, |ui| self.workouts_view(ui));
+                }
+                View::Plan => self.plan_view(ui),
+                View::Treadmill => {
+                    egui::ScrollArea::vertical().show(ui, |ui| self.treadmill_view(ui));
+                }
+                View::Track => {
+                    egui::ScrollArea::vertical().show(ui, |ui| self.track_view(ui));
+                }
+            }
         });
 
         // processing
-        if self.config.contains_key(&self.tmp.remove_config) {
+        if self.config.contains_key(&self.tmp.remove_config)
+            || self.heart_rates.contains_key(&self.tmp.remove_config)
+        {
             self.config.remove(&self.tmp.remove_config);
+            self.ranges.remove(&self.tmp.remove_config);
+            self.heart_rates.remove(&self.tmp.remove_config);
+            // aliases must not refer to removed intensities
+            self.aliases.retain(|_, i| *i != self.tmp.remove_config);
             self.tmp.remove_config = "".to_owned();
         }
+        if self.aliases.contains_key(&self.tmp.remove_alias) {
+            self.aliases.remove(&self.tmp.remove_alias);
+            self.tmp.remove_alias = "".to_owned();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn export_keeps_imported_config() {
+        let mut app = WorkoutApp::default();
+        app.import_paces(
+            r#"
+            max_hr = 190
+            [paces]
+            E = "5:40 @ 65-75% max"
+            T = "4:15"
+            jg = "6:30"
+            [aliases]
+            jog = "jg"
+            [athletes.anna]
+            lthr = 170
+            [athletes.anna.paces]
+            E = "5:10"
+            "#,
+        )
+        .unwrap();
+        assert!(app.insert_pace("T", "4:10"));
+        let exported: Config = toml::from_str(&app.export_paces()).unwrap();
+        assert_eq!(exported.max_hr, Some(190));
+        assert_eq!(exported.athletes["anna"].lthr, Some(170));
+        assert_eq!(exported.athletes["anna"].paces["E"], "5:10");
+        assert_eq!(exported.paces["E"], "5:40 @ 65-75% max");
+        assert_eq!(exported.paces["T"], "4:10");
+        assert_eq!(exported.aliases["jog"], "jg");
+    }
+
+    #[test]
+    fn heart_rate_only_intensities() {
+        let mut app = WorkoutApp::default();
+        app.import_paces(
+            r#"
+            max_hr = 190
+            [paces]
+            E = "5:40"
+            Z1 = "60-70% max"
+            Z2 = "130-145 bpm"
+            [aliases]
+            easy = "Z1"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(app.heart_rates["Z1"], (114, 133));
+        assert_eq!(app.aliases["easy"], "Z1");
+        let paces = paces_to_strings(&app.config, &app.ranges, &app.heart_rates, app.units);
+        assert_eq!(paces["Z2"], "130-145 bpm");
+        let exported: Config = toml::from_str(&app.export_paces()).unwrap();
+        assert_eq!(exported.paces["Z1"], "60-70% max");
+        assert_eq!(exported.paces["Z2"], "130-145 bpm");
+
+        assert!(app.insert_pace("Z3", "80-85% max"));
+        assert_eq!(app.heart_rates["Z3"], (152, 162));
+        assert!(!app.insert_pace("Z4", "90% lthr"));
+        assert!(!app.insert_pace("Z4", ""));
     }
 }
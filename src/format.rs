@@ -0,0 +1,251 @@
+// Canonical text representation of workouts, e.g. "3.2 E + 2 * (1.6 T + 1 min rst)".
+// Parsing the canonical text results in an equivalent workout.
+
+use crate::config::get_units;
+use crate::parse::{self, ParseError};
+use crate::utils::Units;
+use crate::wtree::{RunPart, RunType};
+
+pub fn format_workout(w: &RunPart) -> String {
+    match w {
+        // the top level repetition added by the parser is implicit
+        RunPart::Workout {
+            reps: 1,
+            nodes,
+            recovery: None,
+            ladder: false,
+        } => format_parts(nodes),
+        _ => format_part(w),
+    }
+}
+
+/// Canonical format of a workout string, empty lines stay empty.
+pub fn canonicalize(input: &str) -> Result<String, ParseError> {
+    if input.trim().is_empty() {
+        return Ok(String::new());
+    }
+    // the `?` step and the target are kept, but the target must be reachable
+    let (solved, _) = parse::solve(input)?;
+    let (w, target) = parse::parse_unsolved(input)?;
+    let mut formatted = format_workout(&w);
+    if let Some((rtype, target)) = target {
+        formatted.push_str(&format_target(&rtype, target));
+    }
+    // never rewrite a workout into a different one
+    match parse::parse(&formatted) {
+        Ok(f) if f.is_equivalent(&solved) => Ok(formatted),
+        _ => Err(ParseError {
+            span: 0..input.trim_end().len(),
+            message: format!("can't format the workout, \"{}\" differs", formatted),
+        }),
+    }
+}
+
+// target of a workout with a `?` step, e.g. " = 90 min"
+pub(crate) fn format_target(rtype: &RunType, target: f32) -> String {
+    match rtype {
+        RunType::Time => format!(" = {}", format_time(target)),
+        RunType::Distance => format!(" = {}", format_distance(target)),
+    }
+}
+
+fn format_parts(nodes: &[RunPart]) -> String {
+    let parts: Vec<String> = nodes.iter().map(format_part).collect();
+    parts.join(" + ")
+}
+
+pub(crate) fn format_part(w: &RunPart) -> String {
+    match w {
+        RunPart::Step {
+            time, intensity, ..
+        } if time.0.is_nan() => format!("? {}", intensity),
+        RunPart::Step {
+            rtype: RunType::Distance,
+            distance,
+            intensity,
+            ..
+        } => format!("{} {}", format_distance(*distance), intensity),
+        RunPart::Step {
+            rtype: RunType::Time,
+            time,
+            intensity,
+            ..
+        } => format!("{} {}", format_time(time.0), intensity),
+        RunPart::Workout {
+            nodes,
+            recovery,
+            ladder: true,
+            ..
+        } => format_ladder(nodes, recovery.as_deref()),
+        RunPart::Workout {
+            reps,
+            nodes,
+            recovery,
+            ..
+        } => match recovery {
+            Some(r) => format!("{} * ({}) w/ {}", reps, format_parts(nodes), format_part(r)),
+            None => format!("{} * ({})", reps, format_parts(nodes)),
+        },
+    }
+}
+
+// "ladder(400, 800, 1200) I w/ 2 min jg", elements keep their own intensity if they
+// differ
+fn format_ladder(elements: &[RunPart], recovery: Option<&RunPart>) -> String {
+    let intensity = |n: &RunPart| match n {
+        RunPart::Step { intensity, .. } => intensity.clone(),
+        RunPart::Workout { .. } => String::new(),
+    };
+    let length = |n: &RunPart| match n {
+        RunPart::Step {
+            rtype: RunType::Time,
+            time,
+            ..
+        } => format_time(time.0),
+        // track distances stay in m, e.g. 1200
+        RunPart::Step { distance, .. } if (100.0..100_000.0).contains(distance) => {
+            format_number(*distance, 1)
+        }
+        RunPart::Step { distance, .. } => format_distance(*distance),
+        RunPart::Workout { .. } => String::new(),
+    };
+    let common = elements.first().map_or(String::new(), intensity);
+    let mut formatted = if elements.iter().all(|e| intensity(e) == common) {
+        let lengths: Vec<String> = elements.iter().map(length).collect();
+        format!("ladder({}) {}", lengths.join(", "), common)
+    } else {
+        let parts: Vec<String> = elements
+            .iter()
+            .map(|e| format!("{} {}", length(e), intensity(e)))
+            .collect();
+        format!("ladder({})", parts.join(", "))
+    };
+    if let Some(r) = recovery {
+        formatted.push_str(&format!(" w/ {}", format_part(r)));
+    }
+    formatted
+}
+
+fn format_distance(distance: f32) -> String {
+    // numbers below 100 are km (or miles), everything else m
+    let miles = distance / Units::Imperial.length();
+    let whole_hundredths = ((miles * 100.0).round() - miles * 100.0).abs() < 1e-3;
+    if get_units() == Units::Imperial && whole_hundredths && miles < 100.0 {
+        format_number(miles, 2)
+    } else if (100.0..1000.0).contains(&distance) || distance >= 100_000.0 {
+        format_number(distance, 1)
+    } else if get_units() == Units::Imperial {
+        // metric distances in a mile based workout, e.g. "5 km", keep their unit
+        format!("{} km", format_number(distance / 1000.0, 4))
+    } else {
+        format_number(distance / 1000.0, 4)
+    }
+}
+
+fn format_time(time: f32) -> String {
+    if (time / 60.0).fract().abs() < 1e-4 {
+        format!("{} min", format_number(time / 60.0, 0))
+    } else {
+        format!("{} s", format_number(time, 1))
+    }
+}
+
+// fixed number of decimals without trailing zeros
+fn format_number(x: f32, decimals: usize) -> String {
+    let s = format!("{:.*}", decimals, x);
+    if s.contains('.') {
+        s.trim_end_matches('0').trim_end_matches('.').to_string()
+    } else {
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn canonical_text() {
+        assert_eq!(
+            canonicalize("3.2E+2*(1.6T + 1min rest)+ 30min E").unwrap(),
+            "3.2 E + 2 * (1.6 T + 1 min rst) + 30 min E"
+        );
+        assert_eq!(
+            canonicalize("2E + 8 * (400R + 90s rst) + 1.5 min jg").unwrap(),
+            "2 E + 8 * (400 R + 90 s rst) + 90 s jg"
+        );
+        assert_eq!(
+            canonicalize("0.05 R + 0.4 R + 120 min E").unwrap(),
+            "0.05 R + 400 R + 120 min E"
+        );
+        assert_eq!(
+            canonicalize("8*400R w/90s jg + 2 * (1.6 T) with 1 min rst").unwrap(),
+            "8 * (400 R) w/ 90 s jg + 2 * (1.6 T) w/ 1 min rst"
+        );
+        assert_eq!(
+            canonicalize("10km E~M + 20min M -> T").unwrap(),
+            "10 E->M + 20 min M->T"
+        );
+        assert_eq!(
+            canonicalize("?E + 5*(1km I + 2min jg) + 2E = 90min").unwrap(),
+            "? E + 5 * (1 I + 2 min jg) + 2 E = 90 min"
+        );
+        assert_eq!(
+            canonicalize("ladder(400,800,1200) I w/ 2min jg + 2E").unwrap(),
+            "ladder(400, 800, 1200) I w/ 2 min jg + 2 E"
+        );
+        assert_eq!(
+            canonicalize("ladder(1200T/800I/400R/90s R)").unwrap(),
+            "ladder(1200 T, 800 I, 400 R, 90 s R)"
+        );
+        assert_eq!(
+            canonicalize("3 * (ladder(200, 400) R with 1 min jg)").unwrap(),
+            "3 * (ladder(200, 400) R w/ 1 min jg)"
+        );
+        // equal elements are not mistaken for a recovery
+        assert_eq!(
+            canonicalize("ladder(400, 800) I").unwrap(),
+            "ladder(400, 800) I"
+        );
+        assert_eq!(
+            canonicalize("ladder(400,800,1200,800,400) I").unwrap(),
+            "ladder(400, 800, 1200, 800, 400) I"
+        );
+        assert_eq!(
+            canonicalize("ladder(1min, 2min, 3min) H").unwrap(),
+            "ladder(1 min, 2 min, 3 min) H"
+        );
+        assert!(canonicalize("? E + 10 E = 30 min").is_err());
+        assert_eq!(canonicalize("  ").unwrap(), "");
+        assert!(canonicalize("3X").is_err());
+    }
+
+    #[test]
+    fn canonical_imperial() {
+        crate::config::init_units(Units::Imperial);
+        assert_eq!(
+            canonicalize("2E + 5km T + 4 * (1mi I + 400 jg) + 0.5 E").unwrap(),
+            "2 E + 5 km T + 4 * (1 I + 400 jg) + 0.5 E"
+        );
+        assert_eq!(canonicalize("1.609344km E").unwrap(), "1 E");
+        crate::config::init_units(Units::Metric);
+    }
+
+    #[test]
+    fn roundtrip_sample_plans() {
+        let plans = [
+            include_str!("../sample/2Q.workouts"),
+            include_str!("../sample/2Q_plus.workouts"),
+        ];
+        for line in plans.iter().flat_map(|p| p.lines()) {
+            let w = parse::parse(line).unwrap();
+            let formatted = format_workout(&w);
+            assert!(
+                parse::parse(&formatted).unwrap().is_equivalent(&w),
+                "{}",
+                line
+            );
+            assert_eq!(canonicalize(&formatted).unwrap(), formatted);
+        }
+    }
+}
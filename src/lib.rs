@@ -1,6 +1,15 @@
 // declare internal modules
+mod compare;
 mod config;
+mod format;
+mod macros;
 mod parse;
+#[cfg(feature = "egui")]
+mod plan;
+mod scale;
+mod share;
+mod track;
+mod treadmill;
 mod utils;
 mod wtree;
 
@@ -8,20 +17,56 @@ mod wtree;
 mod egui;
 
 // re-export public Rust API on top level to define crate extenal API
-pub use crate::config::init;
-pub use crate::parse::summarize;
+pub use crate::compare::{compare, group};
+pub use crate::config::{init, init_aliases, init_units, Athlete, Config};
+pub use crate::format::{canonicalize, format_workout};
+pub use crate::macros::{expand_lines, is_definition, Macros};
+pub use crate::parse::{
+    analyze, solve, summarize, try_summarize, IntensityTotals, ParseError, Summary,
+};
+pub use crate::scale::{scale, scale_factor, scale_plan, Scope, Target};
+pub use crate::share::{decode_fragment, encode_fragment};
+pub use crate::track::{track, Track};
+pub use crate::treadmill::{treadmill, Treadmill};
+pub use crate::utils::{Duration, Pace, PaceUnit, Speed, Units};
+pub use crate::wtree::{RunPart, RunType};
 
 #[cfg(feature = "egui")]
 pub use crate::egui::gui_create;
 
+#[cfg(target_arch = "wasm32")]
+use serde::Serialize;
+#[cfg(target_arch = "wasm32")]
+use std::collections::HashMap;
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
 
+// errors are thrown as JS `Error` objects, parse errors additionally have the `start` and
+// `end` (UTF-16 offsets, like JS string indices) of the invalid input
+#[cfg(target_arch = "wasm32")]
+fn js_error(message: &str, span: Option<(usize, usize)>) -> JsValue {
+    let error = js_sys::Error::new(message);
+    if let Some((start, end)) = span {
+        js_sys::Reflect::set(&error, &"start".into(), &(start as u32).into()).ok();
+        js_sys::Reflect::set(&error, &"end".into(), &(end as u32).into()).ok();
+    }
+    error.into()
+}
+
 // define WASM API and implement wrappers
 #[cfg(target_arch = "wasm32")]
 #[wasm_bindgen]
-pub fn wasm_init(paces: JsValue) {
-    config::init(serde_wasm_bindgen::from_value(paces).unwrap())
+pub fn wasm_init(paces: JsValue) -> Result<(), JsValue> {
+    let paces: HashMap<String, String> =
+        serde_wasm_bindgen::from_value(paces).map_err(|e| js_error(&e.to_string(), None))?;
+    // heart rates in bpm only, there is no maximum or threshold heart rate
+    let config = Config {
+        paces: paces.into_iter().collect(),
+        ..Default::default()
+    };
+    config.check().map_err(|e| js_error(&e, None))?;
+    config::init(config.resolved_paces());
+    Ok(())
 }
 
 #[cfg(target_arch = "wasm32")]
@@ -30,8 +75,34 @@ pub fn wasm_summarize(input: &str) -> String {
     parse::summarize(input).unwrap_or("invalid workout".to_string())
 }
 
+/// Summary object with `distance` (m), `duration` (s), `pace` (min/km or min/mi),
+/// `intensities` (totals per intensity) and the parsed `workout` tree.
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+pub fn wasm_analyze(input: &str) -> Result<JsValue, JsValue> {
+    let utf16_offset = |i: usize| input[..i].encode_utf16().count();
+    let summary = parse::analyze(input).map_err(|e| {
+        let span = (utf16_offset(e.span.start), utf16_offset(e.span.end));
+        js_error(&e.message, Some(span))
+    })?;
+    summary
+        .serialize(&serde_wasm_bindgen::Serializer::json_compatible())
+        .map_err(|e| js_error(&e.to_string(), None))
+}
+
 #[cfg(target_arch = "wasm32")]
 #[wasm_bindgen]
 pub fn wasm_gui_create() {
-    egui::gui_create();
+    // start with the workout and paces of a shared link, if any
+    let fragment = web_sys::window()
+        .and_then(|w| w.location().hash().ok())
+        .unwrap_or_default();
+    let (workout, paces, aliases, units) = share::decode_fragment(fragment.trim_start_matches('#'));
+    let config = paces.map(|p| Config {
+        units,
+        paces: p.into_iter().collect(),
+        aliases: aliases.unwrap_or_default().into_iter().collect(),
+        ..Default::default()
+    });
+    egui::gui_create(config, workout);
 }
@@ -0,0 +1,319 @@
+// Named blocks of workout files:
+//
+//     let WU = 3.2E
+//     let CRUISE(n, d) = n * (d T + 1min rst)
+//     let CD = 1.6T + 3.2E
+//     WU + CRUISE(4, 1.6) + CD
+//
+// References are replaced by the text of their definition, with the arguments
+// substituted for the parameters, before the workout is parsed. Definitions may use
+// earlier definitions. A reference right after a repetition ("2 * CD") is put in
+// parentheses.
+
+use std::collections::HashMap;
+
+use crate::config::get_intensities;
+use crate::parse::ParseError;
+
+// words of the workout grammar that can't be used as names
+const KEYWORDS: [&str; 10] = [
+    "let", "min", "s", "km", "mi", "m", "x", "w", "with", "ladder",
+];
+
+#[derive(Debug, Default, Clone)]
+pub struct Macros {
+    defs: HashMap<String, Macro>,
+}
+
+#[derive(Debug, Clone)]
+struct Macro {
+    params: Vec<String>,
+    body: String,
+}
+
+pub fn is_definition(line: &str) -> bool {
+    line.trim_start().starts_with("let ")
+}
+
+/// Expand all lines of a workout file. Definitions are `None`, unless they are invalid.
+pub fn expand_lines<'a>(
+    lines: impl IntoIterator<Item = &'a str>,
+) -> Vec<Option<Result<String, ParseError>>> {
+    let mut macros = Macros::default();
+    lines
+        .into_iter()
+        .map(|l| macros.process(l).transpose())
+        .collect()
+}
+
+impl Macros {
+    /// Handle a line of a workout file: definitions are stored and return `None`, all
+    /// other lines are returned with all references expanded.
+    pub fn process(&mut self, line: &str) -> Result<Option<String>, ParseError> {
+        if is_definition(line) {
+            self.define(line)?;
+            Ok(None)
+        } else {
+            self.expand(line).map(Some)
+        }
+    }
+
+    /// Replace all references in `line`.
+    pub fn expand(&self, line: &str) -> Result<String, ParseError> {
+        self.expand_text(line, 0, &[])
+    }
+
+    fn define(&mut self, line: &str) -> Result<(), ParseError> {
+        // "let" <name> [ "(" <param> { "," <param> } ")" ] "=" <workout>
+        let start = line.find("let ").unwrap() + "let ".len();
+        let eq = line
+            .find('=')
+            .ok_or_else(|| error(line.trim_end().len(), 0, "expected `=`"))?;
+        let head = &line[start..eq];
+        let (name, params) = match head.find('(') {
+            Some(open) => {
+                let close = head
+                    .rfind(')')
+                    .ok_or_else(|| error(eq, 1, "expected `)`"))?;
+                let params: Vec<String> = head[open + 1..close]
+                    .split(',')
+                    .map(|p| p.trim().to_string())
+                    .collect();
+                for p in params.iter() {
+                    if !is_name(p) {
+                        return Err(error(
+                            start + open + 1,
+                            close - open - 1,
+                            "expected parameter name",
+                        ));
+                    }
+                }
+                (head[..open].trim(), params)
+            }
+            None => (head.trim(), Vec::new()),
+        };
+        let name_start = start + head.len() - head.trim_start().len();
+        if !is_name(name) {
+            return Err(error(name_start, name.len(), "expected name"));
+        }
+        if KEYWORDS.contains(&name) || get_intensities().iter().any(|i| i == name) {
+            return Err(error(
+                name_start,
+                name.len(),
+                &format!("{} is already used", name),
+            ));
+        }
+        let body = self.expand_text(&line[eq + 1..], eq + 1, &params)?;
+        if body.trim().is_empty() {
+            return Err(error(line.trim_end().len(), 0, "expected workout"));
+        }
+        self.defs.insert(
+            name.to_string(),
+            Macro {
+                params,
+                body: body.trim().to_string(),
+            },
+        );
+        Ok(())
+    }
+
+    // expand all references of `text`, except the names in `skip` (parameters).
+    // `offset` is the position of `text` within its line, for error spans.
+    fn expand_text(
+        &self,
+        text: &str,
+        offset: usize,
+        skip: &[String],
+    ) -> Result<String, ParseError> {
+        let mut out = String::new();
+        let mut pos = 0;
+        while pos < text.len() {
+            let (name_start, name_end) = match next_name(text, pos) {
+                Some(n) => n,
+                None => break,
+            };
+            out.push_str(&text[pos..name_start]);
+            let name = &text[name_start..name_end];
+            pos = name_end;
+            let def = match self.defs.get(name) {
+                Some(def) if !skip.iter().any(|s| s == name) => def,
+                _ => {
+                    out.push_str(name);
+                    continue;
+                }
+            };
+            let mut body = def.body.clone();
+            if !def.params.is_empty() {
+                let (args, end) = arguments(text, pos)
+                    .ok_or_else(|| error(offset + name_start, name.len(), "expected arguments"))?;
+                if args.len() != def.params.len() {
+                    return Err(error(
+                        offset + name_start,
+                        end - name_start,
+                        &format!("{} expects {} arguments", name, def.params.len()),
+                    ));
+                }
+                let args = args
+                    .iter()
+                    .map(|a| self.expand_text(a, offset, skip))
+                    .collect::<Result<Vec<String>, ParseError>>()?;
+                body = substitute(&body, &def.params, &args);
+                pos = end;
+            }
+            if after_repetition(&out) {
+                out.push_str(&format!("({})", body));
+            } else {
+                out.push_str(&body);
+            }
+        }
+        out.push_str(&text[pos..]);
+        Ok(out)
+    }
+}
+
+fn error(start: usize, len: usize, message: &str) -> ParseError {
+    ParseError {
+        span: start..start + len,
+        message: message.to_string(),
+    }
+}
+
+fn is_name(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+// next word starting at or after `pos` that isn't part of a number or another word,
+// e.g. the "E" of "3.2E" is no name
+fn next_name(text: &str, pos: usize) -> Option<(usize, usize)> {
+    let mut prev = text[..pos].chars().next_back();
+    for (i, c) in text[pos..].char_indices() {
+        let start = pos + i;
+        let preceded = prev.is_some_and(|p| is_name_char(p) || p == '.');
+        if (c.is_ascii_alphabetic() || c == '_') && !preceded {
+            let end = text[start..]
+                .find(|c: char| !is_name_char(c))
+                .map_or(text.len(), |e| start + e);
+            return Some((start, end));
+        }
+        prev = Some(c);
+    }
+    None
+}
+
+// comma separated arguments in parentheses at `pos`, returns them and the end position
+fn arguments(text: &str, pos: usize) -> Option<(Vec<String>, usize)> {
+    let open = pos + text[pos..].len() - text[pos..].trim_start().len();
+    if !text[open..].starts_with('(') {
+        return None;
+    }
+    let mut args = Vec::new();
+    let mut depth = 0;
+    let mut arg_start = open + 1;
+    for (i, c) in text[open..].char_indices() {
+        let i = open + i;
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 1 => {
+                args.push(text[arg_start..i].trim().to_string());
+                return Some((args, i + 1));
+            }
+            ')' => depth -= 1,
+            ',' if depth == 1 => {
+                args.push(text[arg_start..i].trim().to_string());
+                arg_start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+// replace all parameter names in `body` by their arguments
+fn substitute(body: &str, params: &[String], args: &[String]) -> String {
+    let mut out = String::new();
+    let mut pos = 0;
+    while let Some((start, end)) = next_name(body, pos) {
+        out.push_str(&body[pos..start]);
+        let name = &body[start..end];
+        match params.iter().position(|p| p == name) {
+            Some(i) => out.push_str(&args[i]),
+            None => out.push_str(name),
+        }
+        pos = end;
+    }
+    out.push_str(&body[pos..]);
+    out
+}
+
+// the expanded text so far ends with a repetition, "2 *" or "2x"
+fn after_repetition(out: &str) -> bool {
+    let out = out.trim_end();
+    out.ends_with('*')
+        || out
+            .strip_suffix('x')
+            .is_some_and(|o| o.trim_end().ends_with(|c: char| c.is_ascii_digit()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::parse;
+
+    #[test]
+    fn expand_references() {
+        let mut m = Macros::default();
+        assert_eq!(m.process("let WU = 3.2E").unwrap(), None);
+        assert_eq!(
+            m.process("let CRUISE(n, d) = n * (d T + 1min rst)")
+                .unwrap(),
+            None
+        );
+        assert_eq!(m.process("let CD = 1.6T + WU").unwrap(), None);
+        assert_eq!(
+            m.process("WU + CRUISE(4, 1.6) + CD").unwrap().unwrap(),
+            "3.2E + 4 * (1.6 T + 1min rst) + 1.6T + 3.2E"
+        );
+        assert_eq!(m.expand("2 * CD").unwrap(), "2 * (1.6T + 3.2E)");
+        assert_eq!(m.expand("3x WU").unwrap(), "3x (3.2E)");
+        // intensities and units are no references
+        assert_eq!(m.expand("3.2E + 5 min E").unwrap(), "3.2E + 5 min E");
+        assert!(parse(&m.expand("WU + 2 * CD + CRUISE(2, 3.2)").unwrap()).is_ok());
+    }
+
+    #[test]
+    fn workout_file() {
+        let lines = expand_lines(["let WU = 3.2E", "WU + 5T", "let X1 = ", "X1"]);
+        assert!(lines[0].is_none());
+        assert_eq!(lines[1].as_ref().unwrap().as_ref().unwrap(), "3.2E + 5T");
+        assert!(lines[2].as_ref().unwrap().is_err());
+        assert_eq!(lines[3].as_ref().unwrap().as_ref().unwrap(), "X1");
+    }
+
+    #[test]
+    fn invalid_definitions() {
+        let mut m = Macros::default();
+        assert_eq!(
+            m.process("let WU 3.2E").unwrap_err().message,
+            "expected `=`"
+        );
+        assert_eq!(m.process("let E = 3.2M").unwrap_err().span, 4..5);
+        assert_eq!(
+            m.process("let 2WU = 3.2E").unwrap_err().message,
+            "expected name"
+        );
+        m.process("let CRUISE(n, d) = n * (d T + 1min rst)")
+            .unwrap();
+        let e = m.process("3E + CRUISE(4)").unwrap_err();
+        assert_eq!(e.span, 5..14);
+        assert_eq!(e.message, "CRUISE expects 2 arguments");
+        assert_eq!(
+            m.process("CRUISE + 3E").unwrap_err().message,
+            "expected arguments"
+        );
+    }
+}
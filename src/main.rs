@@ -1,6 +1,3 @@
-use serde::{Deserialize, Serialize};
-
-use std::collections::HashMap;
 use std::env;
 use std::fs::File;
 use std::io::BufRead;
@@ -10,9 +7,8 @@ use std::io::Write;
 use std::path::{Path, PathBuf};
 
 // external crates
-use clap::{arg, Parser};
+use clap::{Parser, Subcommand, ValueEnum};
 
-#[allow(dead_code)]
 fn write_file(data: &[u8], path: &Path) {
     let mut file = match File::create(path) {
         Err(why) => panic!("couldn't create {}: {}", path.display(), why),
@@ -23,6 +19,25 @@ fn write_file(data: &[u8], path: &Path) {
     };
 }
 
+// message of a workout that doesn't parse, with the 1-based line and column of the error
+fn invalid_workout(line: usize, e: &workout::ParseError) -> String {
+    format!(
+        "invalid workout: {}:{}: {}",
+        line,
+        e.span.start + 1,
+        e.message
+    )
+}
+
+// value parser of lengths, distances etc. that must be > 0
+fn positive(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(x) if x > 0.0 && x.is_finite() => Ok(x),
+        Ok(_) => Err(format!("{} is not a positive number", s)),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
 fn read_workout_file(path: PathBuf) -> Vec<String> {
     // Open the path in read-only mode, returns `io::Result<File>`
     let file = match File::open(&path) {
@@ -47,6 +62,14 @@ struct Cli {
     #[arg(short, long)]
     config: Option<PathBuf>,
 
+    // athlete of a config file with `[athletes.<name>.paces]` tables
+    #[arg(short, long)]
+    athlete: Option<String>,
+
+    // unit system, overrides the `units` key of the config file
+    #[arg(short, long)]
+    units: Option<UnitsArg>,
+
     // verbose level
     #[arg(short, long, action = clap::ArgAction::Count)]
     verbose: u8,
@@ -56,11 +79,126 @@ struct Cli {
     #[arg(short, long)]
     egui: bool,
 
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    // rewrite a workouts file in canonical format
+    Fmt {
+        file: PathBuf,
+
+        // only report unformatted lines, don't rewrite the file
+        #[arg(long)]
+        check: bool,
+    },
+    // summarize a workout for all athletes of the config file side by side
+    Compare {
+        workout: String,
+    },
+    // group session analysis of a workout for all athletes of the config file
+    Group {
+        workout: String,
+    },
+    // scale the volume of a workouts file, the result is printed
+    Scale {
+        file: PathBuf,
+
+        // target volume in percent of the current volume
+        #[arg(
+            long,
+            required_unless_present = "distance",
+            conflicts_with = "distance",
+            value_parser = positive
+        )]
+        percent: Option<f32>,
+
+        // target total distance (km or miles)
+        #[arg(long, value_parser = positive)]
+        distance: Option<f32>,
+
+        // only scale the easy volume or the quality reps
+        #[arg(long)]
+        only: Option<Only>,
+    },
+    // belt speed (and incline) of every step for running a workout on a treadmill
+    Treadmill {
+        workout: String,
+
+        // speed increment of the treadmill (km/h or mph)
+        #[arg(long, default_value_t = 0.1, value_parser = positive)]
+        increment: f32,
+
+        // add the 1% incline that makes up for the missing air resistance
+        #[arg(long)]
+        incline: bool,
+    },
+    // laps and lap and half lap times of the distance steps for a track session
+    Track {
+        workout: String,
+
+        // track length in m
+        #[arg(long, default_value_t = 400.0, value_parser = positive)]
+        length: f32,
+
+        // lane, outer lanes have longer laps
+        #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..=9))]
+        lane: u8,
+    },
+}
+
+#[derive(Clone, ValueEnum)]
+enum UnitsArg {
+    Metric,
+    Imperial,
+}
+
+#[derive(Clone, ValueEnum)]
+enum Only {
+    Easy,
+    Quality,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Config {
-    paces: HashMap<String, String>,
+// format a workouts file in place. Returns false if the file contains invalid workouts
+// or, in check mode, unformatted lines.
+fn format_file(path: PathBuf, check: bool) -> bool {
+    let mut ok = true;
+    let mut formatted = Vec::new();
+    let mut macros = workout::Macros::default();
+    for (n, line) in read_workout_file(path.clone()).iter().enumerate() {
+        // definitions and lines using them are only checked, the formatter would expand
+        // the references
+        let canonical = match macros.process(line) {
+            Ok(Some(w)) if w == *line => workout::canonicalize(line),
+            Ok(Some(w)) => workout::canonicalize(&w).map(|_| line.clone()),
+            Ok(None) => Ok(line.clone()),
+            Err(e) => Err(e),
+        };
+        match canonical {
+            Ok(f) => {
+                if check && f != *line {
+                    println!("{}:{}: {} -> {}", path.display(), n + 1, line, f);
+                    ok = false;
+                }
+                formatted.push(f);
+            }
+            Err(e) => {
+                eprintln!(
+                    "{}:{}:{}: {}",
+                    path.display(),
+                    n + 1,
+                    e.span.start + 1,
+                    e.message
+                );
+                ok = false;
+            }
+        }
+    }
+    if ok && !check {
+        write_file((formatted.join("\n") + "\n").as_bytes(), &path);
+    }
+    ok
 }
 
 fn main() {
@@ -78,27 +216,149 @@ fn main() {
     let mut f = File::open(c).expect("Couldn't open config file");
     let mut s = String::new();
     f.read_to_string(&mut s).unwrap();
-    let config: Config = toml::from_str(&s).unwrap();
-    workout::init(config.paces);
-
-    if let Some(w) = cli.workout {
-        println!(
-            "{}",
-            workout::summarize(w.as_str()).unwrap_or("invalid workout".to_string())
-        );
+    let mut config: workout::Config = toml::from_str(&s).unwrap();
+    match cli.units {
+        Some(UnitsArg::Metric) => config.units = workout::Units::Metric,
+        Some(UnitsArg::Imperial) => config.units = workout::Units::Imperial,
+        None => {}
+    }
+    if let Err(e) = config.check() {
+        eprintln!("invalid config: {}", e);
+        std::process::exit(1);
+    }
+
+    if let Some(Command::Compare { workout } | Command::Group { workout }) = &cli.command {
+        let athletes: Vec<(String, workout::Config)> = config
+            .athletes
+            .keys()
+            .map(|name| (name.clone(), config.athlete(name).unwrap()))
+            .collect();
+        if athletes.is_empty() {
+            eprintln!("no athletes in config file");
+            std::process::exit(1);
+        }
+        let table = match cli.command {
+            Some(Command::Group { .. }) => workout::group(workout, &athletes),
+            _ => workout::compare(workout, &athletes),
+        };
+        match table {
+            Ok(table) => print!("{}", table),
+            Err(e) => {
+                eprintln!("invalid workout: {}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if let Some(name) = &cli.athlete {
+        config = config.athlete(name).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        });
+    }
+    workout::init(config.resolved_paces());
+    workout::init_aliases(config.aliases.clone().into_iter().collect());
+    workout::init_units(config.units);
+
+    if let Some(Command::Fmt { file, check }) = cli.command {
+        std::process::exit(if format_file(file, check) { 0 } else { 1 });
+    }
+
+    if let Some(Command::Scale {
+        file,
+        percent,
+        distance,
+        only,
+    }) = cli.command
+    {
+        let target = match (percent, distance) {
+            (Some(p), _) => workout::Target::Percent(p),
+            (None, d) => workout::Target::Distance(d.unwrap() * config.units.length()),
+        };
+        let scope = match only {
+            Some(Only::Easy) => workout::Scope::Easy,
+            Some(Only::Quality) => workout::Scope::Quality,
+            None => workout::Scope::All,
+        };
+        let lines = read_workout_file(file);
+        let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+        match workout::scale_plan(&lines, target, scope) {
+            Ok(scaled) => println!("{}", scaled.join("\n")),
+            Err(e) => {
+                eprintln!("can't scale: {}", e.message);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if let Some(Command::Treadmill {
+        workout,
+        increment,
+        incline,
+    }) = &cli.command
+    {
+        let settings = workout::Treadmill {
+            increment: *increment,
+            incline: *incline,
+        };
+        match workout::treadmill(workout, settings) {
+            Ok(table) => print!("{}", table),
+            Err(e) => {
+                eprintln!("invalid workout: {}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if let Some(Command::Track {
+        workout,
+        length,
+        lane,
+    }) = &cli.command
+    {
+        let settings = workout::Track {
+            length: *length,
+            lane: *lane,
+        };
+        match workout::track(workout, settings) {
+            Ok(table) => print!("{}", table),
+            Err(e) => {
+                eprintln!("invalid workout: {}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if let Some(w) = &cli.workout {
+        match workout::try_summarize(w) {
+            Ok(summary) => println!("{}", summary),
+            Err(e) => println!("{}", invalid_workout(1, &e)),
+        }
     }
 
-    if let Some(workouts_file) = cli.file {
-        for w in read_workout_file(workouts_file) {
-            println!(
-                "{}",
-                workout::summarize(&w).unwrap_or("invalid workout".to_string())
-            );
+    let workouts = cli.file.map(read_workout_file);
+    if let Some(workouts) = &workouts {
+        // definition lines have no summary
+        for (n, w) in workout::expand_lines(workouts.iter().map(String::as_str))
+            .into_iter()
+            .enumerate()
+        {
+            match w.map(|w| w.and_then(|w| workout::try_summarize(&w))) {
+                Some(Ok(summary)) => println!("{}", summary),
+                Some(Err(e)) => println!("{}", invalid_workout(n + 1, &e)),
+                None => {}
+            }
         }
     }
 
+    // the GUI starts with the same paces and workouts as the CLI
     #[cfg(feature = "egui")]
     if cli.egui {
-        workout::gui_create().unwrap();
+        let workouts = workouts.map(|w| w.join("\n")).or(cli.workout);
+        workout::gui_create(Some(config), workouts).unwrap();
     }
 }
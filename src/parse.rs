@@ -1,131 +1,651 @@
-use crate::config::{get_intensities, get_pace};
-use crate::utils::pace2speed;
-use crate::wtree::{self, RunPart};
+use crate::config::{canonical_intensity, get_intensities, get_pace, get_units};
+use crate::utils::{hr_range, mid_speed, pace_range, split_target, Duration, Speed};
+use crate::wtree::{self, RunPart, RunType};
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Range;
 
 use log::info;
-use winnow::ascii::digit1;
-use winnow::combinator::separated;
+use serde::Serialize;
+use winnow::ascii::{digit1, multispace0};
+use winnow::combinator::{cut_err, eof, not, opt, peek, preceded, separated, terminated};
+use winnow::error::{ContextError, StrContext, StrContextValue};
+use winnow::stream::Offset;
 use winnow::token::take_while;
 use winnow::{
     combinator::{alt, fail},
-    PResult, Parser,
+    ModalResult, Parser,
 };
 
+/// Error of a failed parse. `span` is the byte range of the offending input within the
+/// workout string.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub span: Range<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(
+            f,
+            "{} (at {}..{})",
+            self.message, self.span.start, self.span.end
+        )
+    }
+}
+
 pub fn summarize(input: &str) -> Option<String> {
-    match parse_workout(&mut normalize_input(input).as_str()) {
-        Ok(w) => {
-            info!("{}", w);
-            Some(format!(
-                "{:.*} km, {}:{:02} h, {}:{:02} min/km",
-                1,
-                w.calc_distance() / 1000.0,
-                w.calc_time() as i32 / 3600,
-                w.calc_time() as i32 % 3600 / 60,
-                (w.calc_time() / (w.calc_distance() / 1000.0)) as i32 / 60,
-                (w.calc_time() / (w.calc_distance() / 1000.0)) as i32 % 60,
-            ))
+    try_summarize(input).ok()
+}
+
+/// Summary of a workout, including the length of a solved `?` step.
+pub fn try_summarize(input: &str) -> Result<String, ParseError> {
+    let (w, solved) = solve(input)?;
+    info!("{}", w);
+    Ok(match solved {
+        Some(step) => format!("{}, ? = {}", summary(&w), step_length(&step)),
+        None => summary(&w),
+    })
+}
+
+// e.g. "42:30 min E" or "6.25 km E"
+fn step_length(step: &RunPart) -> String {
+    match step {
+        RunPart::Step {
+            rtype: RunType::Time,
+            time,
+            intensity,
+            ..
+        } => {
+            format!("{} min {}", time, intensity)
         }
-        Err(_) => None,
+        RunPart::Step {
+            distance,
+            intensity,
+            ..
+        } => {
+            let units = get_units();
+            format!(
+                "{:.2} {} {}",
+                distance / units.length(),
+                units.label(),
+                intensity
+            )
+        }
+        RunPart::Workout { .. } => String::new(),
     }
 }
 
-fn normalize_input(input: &str) -> String {
-    let norm: String = input.split_whitespace().collect();
-    // top level may be list of parts or a repetion. Normalize to always start with a
-    // repetition
-    format!("1*({})", norm)
+/// Distance, duration and pace of a workout. With pace ranges these are ranges as well,
+/// e.g. "17.1-17.9 km, 1:28-1:32 h, 4:55-5:22 min/km". Heart rate only steps have no
+/// distance, their time is listed separately, e.g. "8.0 km + 0:30 h by HR, 1:18 h, ...".
+pub fn summary(w: &RunPart) -> String {
+    let units = get_units();
+    let km = |distance: f32| units.distance(distance);
+    let hours = |time: f32| Duration(time).hours_minutes();
+    let hr_time = w.calc_hr_time();
+    let pace = |time: f32, distance: f32| units.pace(Speed(distance / (time - hr_time)));
+    // the fast bound runs all steps at their high speed, the slow bound at the low one
+    let (slow_distance, fast_distance) = w.distance_range();
+    let (fast_time, slow_time) = w.time_range();
+    if fast_distance == 0.0 && hr_time > 0.0 {
+        return format!("{} h by HR", hours(hr_time));
+    }
+    let mut distance = format!(
+        "{} {}",
+        span(km(slow_distance), km(fast_distance)),
+        units.label()
+    );
+    if hr_time > 0.0 {
+        distance += &format!(" + {} h by HR", hours(hr_time));
+    }
+    format!(
+        "{}, {} h, {} {}",
+        distance,
+        span(hours(fast_time), hours(slow_time)),
+        span(
+            pace(fast_time, fast_distance),
+            pace(slow_time, slow_distance)
+        ),
+        units.pace_unit().label(),
+    )
+}
+
+// "a-b", or just "a" if both bounds look the same
+fn span(a: String, b: String) -> String {
+    if a == b {
+        a
+    } else {
+        format!("{}-{}", a, b)
+    }
+}
+
+/// Structured version of `summary`, including the parsed workout.
+#[derive(Debug, Serialize)]
+pub struct Summary {
+    pub distance: f32, // m
+    pub duration: f32, // s
+    pub pace: String,  // min/km or min/mi
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub distance_range: Option<(f32, f32)>, // m, low and high with pace ranges
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_range: Option<(f32, f32)>, // s
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hr_duration: Option<f32>, // s, heart rate only steps without distance
+    pub intensities: BTreeMap<String, IntensityTotals>,
+    pub workout: RunPart,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub solved: Option<RunPart>, // the solved `?` step
+}
+
+#[derive(Debug, Serialize)]
+pub struct IntensityTotals {
+    pub distance: f32, // m
+    pub duration: f32, // s
+}
+
+pub fn analyze(input: &str) -> Result<Summary, ParseError> {
+    let (w, solved) = solve(input)?;
+    let hr_duration = w.calc_hr_time();
+    Ok(Summary {
+        distance: w.calc_distance(),
+        duration: w.calc_time(),
+        pace: get_units().pace(Speed(w.calc_distance() / (w.calc_time() - hr_duration))),
+        distance_range: Some(w.distance_range()).filter(|(low, high)| low < high),
+        duration_range: Some(w.time_range()).filter(|(low, high)| low < high),
+        hr_duration: Some(hr_duration).filter(|d| *d > 0.0),
+        intensities: w
+            .calc_intensities()
+            .into_iter()
+            .map(|(k, (distance, duration))| (k, IntensityTotals { distance, duration }))
+            .collect(),
+        workout: w,
+        solved,
+    })
+}
+
+pub fn parse(input: &str) -> Result<RunPart, ParseError> {
+    solve(input).map(|(w, _)| w)
+}
+
+/// Parse a workout that may have a `?` step and a target, e.g. `? E + 5 * 1km I = 90min`.
+/// Returns the workout with the solved step and the solved step itself.
+pub fn solve(input: &str) -> Result<(RunPart, Option<RunPart>), ParseError> {
+    let (mut w, target) = parse_unsolved(input)?;
+    // from `start` to the end of the workout, empty at the end like other parse errors
+    let len = input.trim_end().len();
+    let error = |start: usize, message: &str| ParseError {
+        span: start.min(len)..len,
+        message: message.to_string(),
+    };
+    let unknown = input.find('?').unwrap_or(0);
+    let equals = input.rfind('=').unwrap_or(0);
+    match (w.unknowns(), target) {
+        (0, None) => Ok((w, None)),
+        (0, Some(_)) => Err(error(equals, "a target needs a `?` step")),
+        (1, None) => Err(error(len, "expected `=`")),
+        (1, Some((rtype, target))) => match w.solve(rtype, target) {
+            Ok(step) => Ok((w, Some(step))),
+            Err(e) => Err(error(equals, &e)),
+        },
+        _ => {
+            let second = input[unknown + 1..]
+                .find('?')
+                .map_or(unknown, |i| unknown + 1 + i);
+            Err(ParseError {
+                span: second..second + 1,
+                message: "only one `?` step is allowed".to_string(),
+            })
+        }
+    }
+}
+
+// the workout with an unsolved `?` step and its target time (s) or distance (m)
+pub(crate) fn parse_unsolved(input: &str) -> Result<(RunPart, Option<(RunType, f32)>), ParseError> {
+    parse_workout.parse(input).map_err(|e| {
+        // errors after trailing whitespace are reported at the end of the workout
+        let start = e.offset().min(input.trim_end().len());
+        ParseError {
+            span: start..token_end(input, start),
+            message: error_message(e.inner()),
+        }
+    })
+}
+
+// end of the token starting at `start`, used to underline a complete word
+fn token_end(input: &str, start: usize) -> usize {
+    let is_separator = |c: char| c.is_whitespace() || "+*(),/".contains(c);
+    match input[start..].chars().next() {
+        None => start,
+        Some(c) if c.is_whitespace() => start,
+        Some(c) if is_separator(c) => start + c.len_utf8(),
+        _ => input[start..]
+            .find(is_separator)
+            .map_or(input.len(), |i| start + i),
+    }
+}
+
+fn error_message(e: &ContextError) -> String {
+    let mut expected: Vec<String> = Vec::new();
+    for c in e.context() {
+        let value = match c {
+            StrContext::Expected(StrContextValue::Description("intensity")) => {
+                let mut intensities = get_intensities();
+                intensities.sort();
+                format!("intensity ({})", intensities.join(", "))
+            }
+            StrContext::Expected(StrContextValue::Description(d)) => d.to_string(),
+            StrContext::Expected(StrContextValue::StringLiteral(s)) => format!("`{}`", s),
+            StrContext::Expected(StrContextValue::CharLiteral(c)) => format!("`{}`", c),
+            _ => continue,
+        };
+        if !expected.contains(&value) {
+            expected.push(value);
+        }
+    }
+    match expected.split_last() {
+        None => "unexpected input".to_string(),
+        Some((last, [])) => format!("expected {}", last),
+        Some((last, rest)) => format!("expected {} or {}", rest.join(", "), last),
+    }
 }
 
 // --- winnow parser combinator functions ---
 
-pub fn parse_workout(input: &mut &str) -> PResult<wtree::RunPart> {
-    // <rep> "*" "("<parts>")"
-    let (rep, _, _, parts, _) = (digit1, "*", "(", parse_parts, ")").parse_next(input)?;
-    info!("New Workout from: {}", input);
-    let mut w = RunPart::new_workout(rep.parse::<i32>().unwrap());
+pub fn parse_workout(input: &mut &str) -> ModalResult<(RunPart, Option<(RunType, f32)>)> {
+    // <parts> [ "=" ( <time [min|s]> | <distance> [km|m] ) ], the top level is a single
+    // repetition
+    let parts = parse_parts.parse_next(input)?;
+    let target = opt(preceded(ws("="), cut_err(parse_target))).parse_next(input)?;
+    multispace0.parse_next(input)?;
+    cut_err(eof.context(StrContext::Expected('+'.into()))).parse_next(input)?;
+    let mut w = RunPart::new_workout(1);
     if let RunPart::Workout { ref mut nodes, .. } = w {
         *nodes = parts;
     }
+    Ok((w, target))
+}
+
+fn parse_target(input: &mut &str) -> ModalResult<(RunType, f32)> {
+    alt((
+        parse_time.map(|t| (RunType::Time, t.0)),
+        (parse_distance, parse_distance_unit).map(|(d, u)| (RunType::Distance, d * u)),
+        parse_distance.map(|d| (RunType::Distance, default_distance(d))),
+    ))
+    .parse_next(input)
+}
+
+fn parse_repetition(input: &mut &str) -> ModalResult<wtree::RunPart> {
+    // <rep> ( "*" | "x" ) ( "(" <parts> ")" | <step> ) [ ( "w/" | "with" ) <step> ]
+    // look ahead for the "*" first, a number without it is the length of a step
+    peek((ws(digit1), ws(alt(("*", "x"))))).parse_next(input)?;
+    let reps = preceded(
+        multispace0,
+        cut_err(
+            digit1
+                .try_map(str::parse::<i32>)
+                .context(StrContext::Expected(StrContextValue::Description(
+                    "number of repetitions",
+                ))),
+        ),
+    )
+    .parse_next(input)?;
+    ws(alt(("*", "x"))).parse_next(input)?;
+    let parts = cut_err(alt((parse_group, parse_step.map(|s| vec![s])))).parse_next(input)?;
+    // the recovery is only run between repetitions, not after the last one
+    let recovery = opt(preceded(ws(alt(("w/", "with"))), cut_err(parse_step))).parse_next(input)?;
+    info!("New Workout from: {}", input);
+    let mut w = RunPart::new_workout(reps);
+    if let RunPart::Workout {
+        ref mut nodes,
+        recovery: ref mut r,
+        ..
+    } = w
+    {
+        *nodes = parts;
+        *r = recovery.map(Box::new);
+    }
     Ok(w)
 }
 
-fn parse_parts(input: &mut &str) -> PResult<Vec<RunPart>> {
+fn parse_ladder(input: &mut &str) -> ModalResult<wtree::RunPart> {
+    // "ladder" "(" <element> { ( "," | "/" ) <element> } ")" [<effort>]
+    //     [ ( "w/" | "with" ) <step> ]
+    (ws("ladder"), ws("(")).parse_next(input)?;
+    let (elements, _): (Vec<LadderElement>, _) = cut_err((
+        separated(1.., parse_ladder_element, ws(alt((",", "/")))),
+        ws(")")
+            .context(StrContext::Expected(','.into()))
+            .context(StrContext::Expected(')'.into())),
+    ))
+    .parse_next(input)?;
+    // the common intensity is mandatory if an element has no own intensity
+    let start = *input;
+    let effort = if elements.iter().all(|e| e.effort.is_some()) {
+        opt(parse_effort).parse_next(input)?
+    } else {
+        Some(cut_err(parse_effort).parse_next(input)?)
+    };
+    let common_distance = elements
+        .iter()
+        .any(|e| e.rtype == RunType::Distance && e.effort.is_none());
+    if let (Some(effort), true) = (effort, common_distance) {
+        require_pace(input, start, effort)?;
+    }
+    let common = match effort {
+        Some(effort) => Some(speeds(input, start, effort)?),
+        None => None,
+    };
+    let recovery = opt(preceded(ws(alt(("w/", "with"))), cut_err(parse_step))).parse_next(input)?;
+    info!("New ladder from: {}", input);
+
+    let mut w = RunPart::new_ladder();
+    if let RunPart::Workout {
+        ref mut nodes,
+        recovery: ref mut r,
+        ..
+    } = w
+    {
+        *r = recovery.map(Box::new);
+        for e in elements.iter() {
+            let effort = e.effort.or(effort).unwrap();
+            let (speed, range) = e.speeds.or(common).unwrap();
+            let step = match e.rtype {
+                RunType::Distance => {
+                    RunPart::part_from_distance(e.value, speed, &canonical_intensity(effort))
+                }
+                RunType::Time => {
+                    RunPart::part_from_time(Duration(e.value), speed, &canonical_intensity(effort))
+                }
+            };
+            nodes.push(step.with_range(range).with_hr(heart_rate(effort)));
+        }
+    }
+    Ok(w)
+}
+
+// a ladder element, distance (m) or time (s) with an optional own intensity
+struct LadderElement<'s> {
+    rtype: RunType,
+    value: f32,
+    effort: Option<&'s str>,
+    speeds: Option<Speeds>, // of the own intensity
+}
+
+fn parse_ladder_element<'s>(input: &mut &'s str) -> ModalResult<LadderElement<'s>> {
+    // ( <time [min|s]> | <distance> [km|m] ) [<effort>]
+    let (rtype, value) = alt((
+        parse_time.map(|t| (RunType::Time, t.0)),
+        (parse_distance, parse_distance_unit).map(|(d, u)| (RunType::Distance, d * u)),
+        parse_distance.map(|d| (RunType::Distance, default_distance(d))),
+    ))
+    .parse_next(input)?;
+    let start = *input;
+    let effort = opt(parse_effort).parse_next(input)?;
+    if let (Some(effort), RunType::Distance) = (effort, &rtype) {
+        require_pace(input, start, effort)?;
+    }
+    let speeds = match effort {
+        Some(effort) => Some(speeds(input, start, effort)?),
+        None => None,
+    };
+    Ok(LadderElement {
+        rtype,
+        value,
+        effort,
+        speeds,
+    })
+}
+
+fn parse_group(input: &mut &str) -> ModalResult<Vec<RunPart>> {
+    // "(" <parts> ")"
+    let (_, parts, _) = (
+        ws("("),
+        parse_parts,
+        cut_err(
+            ws(")")
+                .context(StrContext::Expected('+'.into()))
+                .context(StrContext::Expected(')'.into())),
+        ),
+    )
+        .parse_next(input)?;
+    Ok(parts)
+}
+
+fn parse_parts(input: &mut &str) -> ModalResult<Vec<RunPart>> {
     // part, { "+", part }
-    separated(1.., parse_part, "+").parse_next(input)
+    separated(1.., parse_part, ws("+")).parse_next(input)
 }
 
-fn parse_part(input: &mut &str) -> PResult<RunPart> {
-    // <workout> | <step>
-    alt((parse_workout, parse_step)).parse_next(input)
+fn parse_part(input: &mut &str) -> ModalResult<RunPart> {
+    // <ladder> | <repetition> | <step>
+    // a part is mandatory wherever it is used, so don't let `separated` backtrack over
+    // the "+" in front of an invalid part
+    cut_err(alt((parse_ladder, parse_repetition, parse_step))).parse_next(input)
 }
 
-fn parse_step(input: &mut &str) -> PResult<wtree::RunPart> {
-    // <time step> | <distance step>
-    alt((parse_time_step, parse_distance_step)).parse_next(input)
+fn parse_step(input: &mut &str) -> ModalResult<wtree::RunPart> {
+    // <unknown step> | <time step> | <distance step>
+    alt((parse_unknown_step, parse_time_step, parse_distance_step)).parse_next(input)
 }
 
-fn parse_distance_step(input: &mut &str) -> PResult<wtree::RunPart> {
-    // <distance> <effort>
-    let (distance, effort) = (parse_distance, parse_effort).parse_next(input)?;
-    info!("New distance step from: {}", input);
-    if distance < 100.0 {
-        // distances below 100 meters (or above 100 km) will be misinterpreted
-        Ok(wtree::RunPart::part_from_distance(
-            distance * 1000.0,
-            pace2speed(&get_pace(effort)).unwrap(),
-        ))
-    } else {
-        Ok(wtree::RunPart::part_from_distance(
-            distance,
-            pace2speed(&get_pace(effort)).unwrap(),
-        ))
+fn parse_unknown_step(input: &mut &str) -> ModalResult<wtree::RunPart> {
+    // "?" <effort>, the length is solved for the target of the workout
+    let start = *input;
+    let (_, effort) = (ws("?"), cut_err(parse_effort)).parse_next(input)?;
+    require_pace(input, start, effort)?;
+    let (speed, range) = speeds(input, start, effort)?;
+    Ok(
+        wtree::RunPart::part_unknown(speed, &canonical_intensity(effort))
+            .with_range(range)
+            .with_hr(heart_rate(effort)),
+    )
+}
+
+// speed of an intensity and the low and high speed of its pace range
+type Speeds = (Speed, (Speed, Speed));
+
+// speeds of an intensity, the speed of the middle pace of its range. Heart rate only
+// intensities have speed 0. Fails at `effort`, which was parsed from `start`, if the
+// pace is invalid, e.g. in a config that was not checked
+fn speeds<'s>(input: &mut &'s str, start: &'s str, effort: &str) -> ModalResult<Speeds> {
+    let pace = get_pace(effort);
+    let speeds = match pace.as_deref().map(split_target) {
+        Some((Some(pace), _)) => pace_range(pace, get_units()).map(|r| (mid_speed(r), r)),
+        Some((None, Some(_))) => Some((Speed(0.0), (Speed(0.0), Speed(0.0)))),
+        _ => None,
+    };
+    match speeds {
+        Some(speeds) => Ok(speeds),
+        None => fail_at(input, start, effort, "intensity with a valid pace"),
     }
 }
 
-fn parse_time_step(input: &mut &str) -> PResult<wtree::RunPart> {
-    // <time [min|s]> <effort>
-    let (time, effort) = (parse_time, parse_effort).parse_next(input)?;
-    info!("New time step from: {}", input);
-    Ok(wtree::RunPart::part_from_time(
-        time,
-        pace2speed(&get_pace(effort)).unwrap(),
+// heart rate target (bpm) of an intensity
+fn heart_rate(effort: &str) -> Option<(u32, u32)> {
+    let pace = get_pace(effort)?;
+    split_target(&pace)
+        .1
+        .and_then(|hr| hr_range(hr, None, None))
+}
+
+// heart rate only intensities can't be used for distances, progressions and `?` steps
+fn require_pace<'s>(input: &mut &'s str, start: &'s str, effort: &str) -> ModalResult<()> {
+    if get_pace(effort).is_some_and(|pace| split_target(&pace).0.is_some()) {
+        return Ok(());
+    }
+    fail_at(input, start, effort, "intensity with a pace")
+}
+
+// fails at `effort`, which was parsed from `start`
+fn fail_at<'s, O>(
+    input: &mut &'s str,
+    start: &'s str,
+    effort: &str,
+    expected: &'static str,
+) -> ModalResult<O> {
+    *input = &start[effort.offset_from(&start)..];
+    cut_err(fail.context(StrContext::Expected(StrContextValue::Description(expected))))
+        .parse_next(input)
+}
+
+// average speed range of a progression from `start` to `end`
+fn ramp_range(start: (Speed, Speed), end: (Speed, Speed)) -> (Speed, Speed) {
+    (
+        Speed((start.0 .0 + end.0 .0) / 2.0),
+        Speed((start.1 .0 + end.1 .0) / 2.0),
+    )
+}
+
+fn parse_distance_step(input: &mut &str) -> ModalResult<wtree::RunPart> {
+    // <distance> [km|m] <effort> [ ( "->" | "~" ) <effort> ]
+    let start = *input;
+    let (distance, effort) = alt((
+        (parse_distance, parse_distance_unit, parse_effort).map(|(d, u, e)| (d * u, e)),
+        (parse_distance, parse_effort).map(|(d, e)| (default_distance(d), e)),
     ))
+    .parse_next(input)?;
+    require_pace(input, start, effort)?;
+    let end = opt(parse_ramp_end).parse_next(input)?;
+    if let Some(end) = end {
+        require_pace(input, start, end)?;
+    }
+    info!("New distance step from: {}", input);
+    let (speed, range) = speeds(input, start, effort)?;
+    Ok(match end {
+        Some(end) => {
+            let (end_speed, end_range) = speeds(input, start, end)?;
+            wtree::RunPart::ramp_from_distance(
+                distance,
+                speed,
+                end_speed,
+                &ramp_intensity(effort, end),
+            )
+            .with_range(ramp_range(range, end_range))
+        }
+        None => wtree::RunPart::part_from_distance(distance, speed, &canonical_intensity(effort))
+            .with_range(range)
+            .with_hr(heart_rate(effort)),
+    })
 }
 
-fn parse_distance(input: &mut &str) -> PResult<f32> {
+fn parse_time_step(input: &mut &str) -> ModalResult<wtree::RunPart> {
+    // <time [min|s]> <effort> [ ( "->" | "~" ) <effort> ]
+    let start = *input;
+    let (time, effort, end) = (parse_time, parse_effort, opt(parse_ramp_end)).parse_next(input)?;
+    if let Some(end) = end {
+        require_pace(input, start, effort)?;
+        require_pace(input, start, end)?;
+    }
+    info!("New time step from: {}", input);
+    let (speed, range) = speeds(input, start, effort)?;
+    Ok(match end {
+        Some(end) => {
+            let (end_speed, end_range) = speeds(input, start, end)?;
+            wtree::RunPart::ramp_from_time(time, speed, end_speed, &ramp_intensity(effort, end))
+                .with_range(ramp_range(range, end_range))
+        }
+        None => wtree::RunPart::part_from_time(time, speed, &canonical_intensity(effort))
+            .with_range(range)
+            .with_hr(heart_rate(effort)),
+    })
+}
+
+fn parse_ramp_end<'s>(input: &mut &'s str) -> ModalResult<&'s str> {
+    // ( "->" | "~" ) <effort>, the end intensity of a progression
+    preceded(ws(alt(("->", "~"))), cut_err(parse_effort)).parse_next(input)
+}
+
+// intensity name of a progression, e.g. "E->M"
+fn ramp_intensity(start: &str, end: &str) -> String {
+    format!(
+        "{}->{}",
+        canonical_intensity(start),
+        canonical_intensity(end)
+    )
+}
+
+fn parse_distance(input: &mut &str) -> ModalResult<f32> {
     // <distance>
-    let distance: &str = take_while(1.., is_float_digit).parse_next(input)?;
-    Ok(distance.parse::<f32>().unwrap())
+    parse_number.parse_next(input)
+}
+
+fn parse_distance_unit(input: &mut &str) -> ModalResult<f32> {
+    // [km|mi|m], factor to meters
+    let unit = ws(alt((
+        "km",
+        terminated("mi", not("n")),
+        terminated("m", not("in")),
+    )))
+    .parse_next(input)?;
+    Ok(match unit {
+        "km" => 1000.0,
+        "mi" => 1609.344,
+        _ => 1.0,
+    })
 }
 
-fn parse_time(input: &mut &str) -> PResult<f32> {
+// meters of a distance without unit: numbers below 100 are km (miles with imperial
+// units), everything else meters. Distances below 100 meters (or above 100 km) will be
+// misinterpreted.
+fn default_distance(d: f32) -> f32 {
+    if d < 100.0 {
+        d * get_units().length()
+    } else {
+        d
+    }
+}
+
+fn parse_number(input: &mut &str) -> ModalResult<f32> {
+    ws(take_while(1.., is_float_digit))
+        .try_map(str::parse::<f32>)
+        .context(StrContext::Expected(StrContextValue::Description("number")))
+        .parse_next(input)
+}
+
+fn parse_time(input: &mut &str) -> ModalResult<Duration> {
     // <time [min|s]>
-    let time: &str = take_while(1.., is_float_digit).parse_next(input)?;
-    let unit: &str = alt(("min", "s", fail)).parse_next(input)?;
-    Ok(time.parse::<f32>().unwrap()
-        * match unit {
+    let time = parse_number.parse_next(input)?;
+    let unit: &str = ws(alt(("min", "s", fail))).parse_next(input)?;
+    Ok(Duration(
+        time * match unit {
             "min" => 60.0,
             "s" => 1.0,
             _ => panic!("unknown time unit"), // should never happen
-        })
+        },
+    ))
+}
+
+// skip whitespace in front of a token
+fn ws<'s, O>(
+    parser: impl Parser<&'s str, O, ContextError>,
+) -> impl Parser<&'s str, O, ContextError> {
+    preceded(multispace0, parser)
 }
 
 fn is_float_digit(c: char) -> bool {
     c.is_ascii_digit() || c == '.'
 }
 
-fn parse_effort<'s>(input: &mut &'s str) -> PResult<&'s str> {
+fn parse_effort<'s>(input: &mut &'s str) -> ModalResult<&'s str> {
     // the alt combinator requires its alternatives in a tuple at compile time.
     // Since our intensities are only known during runtime we can't use it here.
-
-    for i in get_intensities() {
-        let intensity: PResult<&str> = i.as_str().parse_next(input);
-        match intensity {
-            Ok(x) => return Ok(x),
-            _ => (),
+    // Longest names are tried first, otherwise "H" would shadow "HM".
+    multispace0.parse_next(input)?;
+    let mut intensities = get_intensities();
+    intensities.sort_by_key(|i| std::cmp::Reverse(i.len()));
+    for i in intensities {
+        let intensity: ModalResult<&str> = i.as_str().parse_next(input);
+        if let Ok(x) = intensity {
+            return Ok(x);
         }
     }
-    fail(input)
+    fail.context(StrContext::Expected(StrContextValue::Description(
+        "intensity",
+    )))
+    .parse_next(input)
 }
 
 // --- tests -----------------------------
@@ -133,6 +653,7 @@ fn parse_effort<'s>(input: &mut &'s str) -> PResult<&'s str> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::utils::Units;
     use approx::assert_abs_diff_eq;
     #[test]
     fn test_parse_distance() {
@@ -148,11 +669,15 @@ mod tests {
     fn test_parse_time() {
         // Ok
         let mut input = "20min";
-        assert_abs_diff_eq!(parse_time(&mut input).unwrap(), 20.0 * 60.0, epsilon = 0.01);
+        assert_abs_diff_eq!(
+            parse_time(&mut input).unwrap().0,
+            20.0 * 60.0,
+            epsilon = 0.01
+        );
         let mut input = "1.5min";
-        assert_abs_diff_eq!(parse_time(&mut input).unwrap(), 90.0, epsilon = 0.01);
+        assert_abs_diff_eq!(parse_time(&mut input).unwrap().0, 90.0, epsilon = 0.01);
         let mut input = "60s";
-        assert_abs_diff_eq!(parse_time(&mut input).unwrap(), 60.0, epsilon = 0.01);
+        assert_abs_diff_eq!(parse_time(&mut input).unwrap().0, 60.0, epsilon = 0.01);
         // Err
         let mut input = "20";
         assert!(parse_time(&mut input).is_err());
@@ -196,7 +721,7 @@ mod tests {
 
     #[test]
     fn single_step_workout() {
-        let w = parse_workout(&mut normalize_input("3jog").as_str()).unwrap();
+        let (w, _) = parse_workout(&mut "3jog").unwrap();
 
         if let RunPart::Workout { ref nodes, .. } = w {
             assert_eq!(nodes.len(), 1);
@@ -207,7 +732,7 @@ mod tests {
 
     #[test]
     fn multi_step_workout() {
-        let w = parse_workout(&mut normalize_input("3 M + 3 T").as_str()).unwrap();
+        let (w, _) = parse_workout(&mut "3 M + 3 T").unwrap();
         if let RunPart::Workout { ref nodes, .. } = w {
             assert_eq!(nodes.len(), 2);
             assert_abs_diff_eq!(w.calc_distance(), 6000_f32, epsilon = 0.1);
@@ -221,8 +746,7 @@ mod tests {
 
     #[test]
     fn repeats() {
-        let w =
-            parse_workout(&mut normalize_input("2min I + 3*(1min H + 5min jg)").as_str()).unwrap();
+        let (w, _) = parse_workout(&mut "2min I + 3*(1min H + 5min jg)").unwrap();
         if let RunPart::Workout { ref nodes, .. } = w {
             assert_eq!(nodes.len(), 2);
             assert_abs_diff_eq!(
@@ -235,11 +759,9 @@ mod tests {
 
     #[test]
     fn repeats_2() {
-        let w = parse_workout(
-            &mut normalize_input("10 min E + 5 * (3 min I + 2 min jg) + 6 * (1 min R + 2 min jg)")
-                .as_str(),
-        )
-        .unwrap();
+        let (w, _) =
+            parse_workout(&mut "10 min E + 5 * (3 min I + 2 min jg) + 6 * (1 min R + 2 min jg)")
+                .unwrap();
 
         if let RunPart::Workout { ref nodes, .. } = w {
             assert_eq!(nodes.len(), 3);
@@ -251,6 +773,145 @@ mod tests {
         }
     }
 
+    #[test]
+    fn recovery_shorthand() {
+        // no recovery after the last repetition
+        let w = parse("8 * 400 R w/ 90s jg").unwrap();
+        assert_abs_diff_eq!(w.calc_time(), 8.0 * 84.0 + 7.0 * 90.0, epsilon = 0.1);
+        let with = parse("8 * (400 R) with 90s jg").unwrap();
+        assert!(with.is_equivalent(&w));
+        assert!(!parse("8 * (400 R + 90s jg)").unwrap().is_equivalent(&w));
+
+        let e = parse("8 * 400 R w/ 90s").unwrap_err();
+        assert!(e.message.starts_with("expected intensity"));
+    }
+
+    #[test]
+    fn compact_repeats() {
+        let verbose = parse("8 * (400 R)").unwrap();
+        assert!(parse("8x400R").unwrap().is_equivalent(&verbose));
+        assert!(parse("8 x 400m R").unwrap().is_equivalent(&verbose));
+        assert!(parse("6 x 1km I")
+            .unwrap()
+            .is_equivalent(&parse("6 * (1 I)").unwrap()));
+        assert!(parse("3x(2km T + 2min jg)")
+            .unwrap()
+            .is_equivalent(&parse("3 * (2 T + 2 min jg)").unwrap()));
+        // explicit units override the meters/km heuristic
+        assert_abs_diff_eq!(parse("50 m R").unwrap().calc_distance(), 50.0);
+        assert_abs_diff_eq!(parse("200km E").unwrap().calc_distance(), 200_000.0);
+        assert!(parse("1 min x").is_err());
+    }
+
+    #[test]
+    fn ladders() {
+        let w = parse("ladder(400,800,1200,800,400) I w/ 2min jg").unwrap();
+        let expanded = parse(
+            "1 * (400 I + 2 min jg + 800 I + 2 min jg + 1200 I + 2 min jg + 800 I \
+             + 2 min jg + 400 I)",
+        )
+        .unwrap();
+        let same_totals = |a: &RunPart, b: &RunPart| {
+            let close = |x: f32, y: f32| (x - y).abs() < 0.1;
+            let (ta, tb) = (a.calc_intensities(), b.calc_intensities());
+            ta.len() == tb.len()
+                && ta
+                    .iter()
+                    .zip(tb.iter())
+                    .all(|((ia, a), (ib, b))| ia == ib && close(a.0, b.0) && close(a.1, b.1))
+        };
+        assert!(same_totals(&w, &expanded));
+        // the elements are kept, the recovery is stored once
+        if let RunPart::Workout { nodes, .. } = &w {
+            if let RunPart::Workout {
+                nodes,
+                recovery,
+                ladder,
+                ..
+            } = &nodes[0]
+            {
+                assert!(ladder);
+                assert_eq!(nodes.len(), 5);
+                assert_eq!(recovery.as_ref().unwrap().calc_time(), 120.0);
+            }
+        }
+
+        // per element intensities, the common intensity is optional then
+        let w = parse("ladder(1200T/800I/400R) w/ 90s jg").unwrap();
+        let expanded = parse("1 * (1200 T + 90 s jg + 800 I + 90 s jg + 400 R)").unwrap();
+        assert!(same_totals(&w, &expanded));
+        let w = parse("2E + ladder(1min, 2min, 3 min) H + 2E").unwrap();
+        assert_abs_diff_eq!(w.calc_time(), 2.0 * 720.0 + 360.0, epsilon = 0.1);
+        let w = parse("ladder(1km, 2 km I) T").unwrap();
+        assert_abs_diff_eq!(w.calc_time(), 270.0 + 480.0, epsilon = 0.1);
+
+        let e = parse("ladder(400, 800) + 2E").unwrap_err();
+        assert_eq!(e.span, 17..18);
+        assert!(e.message.starts_with("expected intensity"));
+        let e = parse("ladder(400 800) I").unwrap_err();
+        assert_eq!(e.message, "expected `,` or `)`");
+    }
+
+    #[test]
+    fn progressions() {
+        // linear speed ramp from 6:00 (E) to 5:00 (M) min/km
+        let w = parse("12km E->M").unwrap();
+        let average = (1000.0 / 360.0 + 1000.0 / 300.0) / 2.0;
+        assert_abs_diff_eq!(w.calc_time(), 12000.0 / average, epsilon = 0.1);
+        assert_eq!(w, parse("12 E ~ M").unwrap());
+        let w = parse("20min M~T").unwrap();
+        assert_abs_diff_eq!(
+            w.calc_distance(),
+            1200.0 * (1000.0 / 300.0 + 1000.0 / 270.0) / 2.0,
+            epsilon = 0.1
+        );
+        assert_eq!(analyze("20 min M->T").unwrap().intensities.len(), 1);
+        assert!(analyze("20 min M->T")
+            .unwrap()
+            .intensities
+            .contains_key("M->T"));
+        assert!(parse("2 * (5 min rest~jog)").is_ok());
+
+        let e = parse("20min M->").unwrap_err();
+        assert!(e.message.starts_with("expected intensity"));
+    }
+
+    #[test]
+    fn solve_unknown_step() {
+        // 2 km E (12 min) + 5 * (1 km I (4 min) + 2 min jg) = 42 min
+        let (w, step) = solve("? E + 5*(1km I + 2min jg) + 2E = 90min").unwrap();
+        assert_abs_diff_eq!(w.calc_time(), 5400.0, epsilon = 0.1);
+        assert_abs_diff_eq!(step.unwrap().calc_time(), 48.0 * 60.0, epsilon = 0.1);
+        let (w, _) = solve("?E + 5*(1km I + 2min jg) + 2E = 20km").unwrap();
+        assert_abs_diff_eq!(w.calc_distance(), 20000.0, epsilon = 0.1);
+        assert_eq!(
+            summarize("? E + 5*(1km I + 2min jg) + 2E = 90min").unwrap(),
+            "16.2 km, 1:30 h, 5:32 min/km, ? = 48:00 min E"
+        );
+
+        let e = parse("? E + 10 E = 30 min").unwrap_err();
+        assert_eq!(e.span, 11..19);
+        assert_eq!(e.message, "the target is reached without the `?` step");
+        let e = parse("? E + 10 E").unwrap_err();
+        assert_eq!(e.message, "expected `=`");
+        assert_eq!(e.span, 10..10);
+        assert_eq!(parse("? E  ").unwrap_err().span, 3..3);
+        assert_eq!(
+            parse("10 E = 90 min").unwrap_err().message,
+            "a target needs a `?` step"
+        );
+        assert_eq!(parse("? E + ? M = 90 min").unwrap_err().span, 6..7);
+        // a `?` recovery of a ladder is run between all elements
+        let (w, step) = solve("ladder(400,800,1200) I w/ ? jg = 30min").unwrap();
+        assert_abs_diff_eq!(
+            step.unwrap().calc_time(),
+            (1800.0 - 576.0) / 2.0,
+            epsilon = 0.1
+        );
+        assert_abs_diff_eq!(w.calc_time(), 1800.0, epsilon = 0.1);
+        assert!(parse("? E = ").is_err());
+    }
+
     #[test]
     fn summaries() {
         assert_eq!(
@@ -260,13 +921,146 @@ mod tests {
         );
         assert_eq!(
             summarize("2E + 2 * ( 5 * (4 min I + 90s jg)) + 2 E").unwrap(),
-            "15.9 km, 1:19 h, 4:58 min/km".to_string()
+            "15.9 km, 1:19 h, 4:59 min/km".to_string()
         );
     }
 
+    #[test]
+    fn imperial_units() {
+        // paces of the config are per mile, distances without unit are miles
+        crate::config::init_units(Units::Imperial);
+        assert_eq!(
+            summarize("5 E + 1 mi T + 1609.344 m T").unwrap(),
+            "7.0 mi, 0:39 h, 5:34 min/mi"
+        );
+        assert_eq!(
+            try_summarize("? E + 3 E = 10 mi").unwrap(),
+            "10.0 mi, 1:00 h, 6:00 min/mi, ? = 7.00 mi E"
+        );
+        let s = analyze("5 E").unwrap();
+        assert_abs_diff_eq!(s.distance, 8046.72, epsilon = 0.01);
+        assert_abs_diff_eq!(s.duration, 1800.0, epsilon = 0.01);
+        crate::config::init_units(Units::Metric);
+    }
+
+    #[test]
+    fn pace_ranges() {
+        // LT is 4:10-4:20
+        let w = "3 E + 4 * (2 LT + 2 min jg) + 30 min LT";
+        assert_eq!(
+            summarize(w).unwrap(),
+            "18.9-19.2 km, 1:29-1:31 h, 4:39-4:47 min/km"
+        );
+        let s = analyze(w).unwrap();
+        let (low, high) = s.distance_range.unwrap();
+        assert_abs_diff_eq!(low, 12000.0 + 1800.0 / 0.26, epsilon = 0.1);
+        assert_abs_diff_eq!(high, 19200.0, epsilon = 0.1);
+        assert_abs_diff_eq!(s.duration_range.unwrap().1, 5440.0, epsilon = 0.1);
+        assert_eq!(analyze("3 E").unwrap().distance_range, None);
+
+        let (w, _) = parse_workout(&mut "2 LT + 10 min E->LT").unwrap();
+        if let RunPart::Workout { nodes, .. } = w {
+            assert_eq!(nodes[0].pace(), "4:10-4:20");
+            assert_abs_diff_eq!(nodes[0].calc_time(), 510.0, epsilon = 0.1);
+            let (low, high) = nodes[1].distance_range();
+            assert_abs_diff_eq!(low, 600.0 * (1.0 / 0.36 + 1.0 / 0.26) / 2.0, epsilon = 0.1);
+            assert_abs_diff_eq!(high, 600.0 * (1.0 / 0.36 + 1.0 / 0.25) / 2.0, epsilon = 0.1);
+        }
+    }
+
+    #[test]
+    fn heart_rates() {
+        // Z2 is 130-145 bpm without a pace, LR is 6:15 @ 140-150 bpm
+        let w = "2 E + 30 min Z2 + 2 E";
+        assert_eq!(
+            summarize(w).unwrap(),
+            "4.0 km + 0:30 h by HR, 0:54 h, 6:00 min/km"
+        );
+        assert_eq!(summarize("45 min Z2").unwrap(), "0:45 h by HR");
+        assert_eq!(analyze("45 min Z2").unwrap().pace, "-");
+        let s = analyze(w).unwrap();
+        assert_eq!(s.pace, "6:00");
+        assert_abs_diff_eq!(s.hr_duration.unwrap(), 1800.0, epsilon = 0.1);
+        assert_eq!(analyze("3 E").unwrap().hr_duration, None);
+
+        let w = parse("10 LR + 30 min Z2").unwrap();
+        if let RunPart::Workout { nodes, .. } = w {
+            assert_eq!(
+                nodes[0].to_string(),
+                "10.0 km @ 6:15 min/km pace, 140-150 bpm"
+            );
+            assert_eq!(nodes[1].to_string(), "30:00 min @ 130-145 bpm");
+            assert_eq!(nodes[1].calc_distance(), 0.0);
+        }
+
+        // heart rate only intensities need a time
+        for (w, span) in [
+            ("5 Z2", 2..4),
+            ("2 km Z2", 5..7),
+            ("10 min E->Z2", 10..12),
+            ("10 min Z2->E", 7..12),
+            ("? Z2 + 2 E = 60 min", 2..4),
+            ("ladder(400,800) Z2", 16..18),
+            ("ladder(400 Z2, 2 min Z2) E", 11..13),
+        ] {
+            let e = parse(w).unwrap_err();
+            assert_eq!(e.message, "expected intensity with a pace", "{}", w);
+            assert_eq!(e.span, span, "{}", w);
+        }
+        assert!(parse("ladder(2 min, 4 min) Z2").is_ok());
+        let (_, solved) = solve("? LR + 30 min Z2 = 90 min").unwrap();
+        assert_eq!(
+            solved.unwrap().to_string(),
+            "1:00:00 min @ 6:15 min/km pace, 140-150 bpm"
+        );
+    }
+
+    #[test]
+    fn parse_error_spans() {
+        let e = parse("3.2E + 2 * (1.6X + 1 min rest)").unwrap_err();
+        assert_eq!(e.span, 15..16);
+        assert!(e.message.starts_with("expected intensity"));
+
+        let e = parse("3E + 2 * (1.6T").unwrap_err();
+        assert_eq!(e.span, 14..14);
+        assert_eq!(e.message, "expected `+` or `)`");
+
+        let e = parse("3E + foo").unwrap_err();
+        assert_eq!(e.span, 5..8);
+        assert_eq!(e.message, "expected number");
+
+        let e = parse("3E + ").unwrap_err();
+        assert_eq!(e.span, 4..4);
+
+        // too many repetitions for an i32
+        let e = parse("2E + 99999999999x400R").unwrap_err();
+        assert_eq!(e.span, 5..21);
+        assert_eq!(e.message, "expected number of repetitions");
+    }
+
+    #[test]
+    fn structured_summary() {
+        let s = analyze("3E + 2 * (1 min I + 1 min jg)").unwrap();
+        assert_abs_diff_eq!(s.distance, 3000.0 + 2.0 * (250.0 + 125.0), epsilon = 0.1);
+        assert_abs_diff_eq!(s.duration, 3.0 * 360.0 + 240.0, epsilon = 0.1);
+        assert_eq!(s.pace, "5:52");
+        assert_eq!(
+            s.intensities.keys().collect::<Vec<_>>(),
+            vec!["E", "I", "jg"]
+        );
+        assert_abs_diff_eq!(s.intensities["I"].duration, 120.0, epsilon = 0.1);
+        assert!(analyze("3X").is_err());
+
+        // aliases are reported with the name of their intensity
+        let s = analyze("1 min jog + 2 min jg").unwrap();
+        assert_eq!(s.intensities.keys().collect::<Vec<_>>(), vec!["jg"]);
+        assert_abs_diff_eq!(s.intensities["jg"].duration, 180.0, epsilon = 0.1);
+    }
+
     #[test]
     fn summary_invalid_inputs() {
         // basically make sure that we are not crashing with invalid input
         assert_eq!(summarize("bla"), None);
+        assert_eq!(summarize(". E"), None);
     }
 }
@@ -0,0 +1,72 @@
+// Training plans are workout files with one workout per line. Weeks are separated by
+// empty lines, plans without any empty line are split into weeks of `per_week` workouts.
+// Definitions (`let WU = 3.2E`) are no workouts.
+
+use crate::macros::is_definition;
+use crate::parse;
+
+/// Group the lines of a plan into weeks, the line numbers of the workouts of each week.
+pub fn weeks(lines: &[&str], per_week: usize) -> Vec<Vec<usize>> {
+    let mut weeks: Vec<Vec<usize>> = Vec::new();
+    if lines.iter().any(|l| l.trim().is_empty()) {
+        let mut week = Vec::new();
+        for (n, line) in lines.iter().enumerate() {
+            if is_definition(line) {
+                continue;
+            } else if !line.trim().is_empty() {
+                week.push(n);
+            } else if !week.is_empty() {
+                weeks.push(std::mem::take(&mut week));
+            }
+        }
+        if !week.is_empty() {
+            weeks.push(week);
+        }
+    } else {
+        let numbers: Vec<usize> = (0..lines.len())
+            .filter(|&n| !is_definition(lines[n]))
+            .collect();
+        weeks = numbers
+            .chunks(per_week.max(1))
+            .map(|c| c.to_vec())
+            .collect();
+    }
+    weeks
+}
+
+/// Total distance (m) and time (s) of all valid workouts, invalid workouts are skipped.
+pub fn totals<'a>(workouts: impl IntoIterator<Item = &'a str>) -> (f32, f32) {
+    workouts
+        .into_iter()
+        .filter_map(|w| parse::parse(w).ok())
+        .fold((0.0, 0.0), |(d, t), w| {
+            (d + w.calc_distance(), t + w.calc_time())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn weeks_by_count() {
+        let lines = ["3E", "3M", "3T", "3E", "3M"];
+        assert_eq!(weeks(&lines, 3), vec![vec![0, 1, 2], vec![3, 4]]);
+    }
+
+    #[test]
+    fn weeks_by_empty_lines() {
+        let lines = ["3E", "3M", "", "3T", " ", "", "3E"];
+        assert_eq!(weeks(&lines, 3), vec![vec![0, 1], vec![3], vec![6]]);
+        let lines = ["let WU = 3.2E", "WU + 3M", "", "3T"];
+        assert_eq!(weeks(&lines, 3), vec![vec![1], vec![3]]);
+    }
+
+    #[test]
+    fn week_totals() {
+        let (distance, time) = totals(["3E", "invalid", "10 min M"]);
+        assert_abs_diff_eq!(distance, 5000.0, epsilon = 0.1);
+        assert_abs_diff_eq!(time, 3.0 * 6.0 * 60.0 + 600.0, epsilon = 0.1);
+    }
+}
@@ -0,0 +1,353 @@
+// Scale the volume of workouts and plans, e.g. to 85% during illness recovery.
+//
+// Steps at E pace or slower are the easy volume (warm-up, cool-down, long runs, recovery
+// jogs), faster steps are quality. Without an E intensity all steps are easy. Easy
+// distances and times are scaled and rounded to 0.1 km (or mile) or 1 min. Repetitions
+// with quality steps have their rep counts scaled, ladders the length of their elements
+// (rounded to 100 m or 15 s). The structure of the workouts is kept.
+
+use crate::config::{get_pace, get_units};
+use crate::format::{format_target, format_workout};
+use crate::macros::expand_lines;
+use crate::parse::{self, ParseError};
+use crate::utils::{pace_range, split_target, Duration, Speed};
+use crate::wtree::{RunPart, RunType};
+
+/// Which part of the volume is scaled.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scope {
+    All,
+    Easy,
+    Quality,
+}
+
+/// Target volume, relative or absolute.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Target {
+    Percent(f32),
+    Distance(f32), // m
+}
+
+/// Scale `w` by `factor`, only the volume of `scope` is changed.
+pub fn scale(w: &RunPart, factor: f32, scope: Scope) -> RunPart {
+    match w {
+        // the top level repetition added by the parser
+        RunPart::Workout {
+            reps: 1,
+            nodes,
+            recovery: None,
+            ladder: false,
+        } => RunPart::Workout {
+            reps: 1,
+            nodes: nodes.iter().map(|n| scale_part(n, factor, scope)).collect(),
+            recovery: None,
+            ladder: false,
+        },
+        _ => scale_part(w, factor, scope),
+    }
+}
+
+fn scale_part(w: &RunPart, factor: f32, scope: Scope) -> RunPart {
+    let quality = is_quality(w);
+    match w {
+        _ if !in_scope(w, scope) => w.clone(),
+        RunPart::Workout {
+            reps,
+            nodes,
+            recovery,
+            ladder: true,
+        } => {
+            let rung = |n: &RunPart| {
+                if in_scope(n, scope) {
+                    resize(n, factor, 100.0, 15.0)
+                } else {
+                    n.clone()
+                }
+            };
+            RunPart::Workout {
+                reps: *reps,
+                nodes: nodes.iter().map(rung).collect(),
+                recovery: recovery.as_deref().map(|r| Box::new(rung(r))),
+                ladder: true,
+            }
+        }
+        RunPart::Workout {
+            reps,
+            nodes,
+            recovery,
+            ..
+        } if quality => RunPart::Workout {
+            reps: ((*reps as f32 * factor).round() as i32).max(1),
+            nodes: nodes.clone(),
+            recovery: recovery.clone(),
+            ladder: false,
+        },
+        // repetitions of easy steps, e.g. a long run with breaks
+        RunPart::Workout {
+            reps,
+            nodes,
+            recovery,
+            ..
+        } => RunPart::Workout {
+            reps: *reps,
+            nodes: nodes.iter().map(|n| scale_part(n, factor, scope)).collect(),
+            recovery: recovery
+                .as_ref()
+                .map(|r| Box::new(scale_part(r, factor, scope))),
+            ladder: false,
+        },
+        RunPart::Step { .. } => resize(w, factor, get_units().length() / 10.0, 60.0),
+    }
+}
+
+fn in_scope(w: &RunPart, scope: Scope) -> bool {
+    match scope {
+        Scope::All => true,
+        Scope::Easy => !is_quality(w),
+        Scope::Quality => is_quality(w),
+    }
+}
+
+// highest speed of easy steps, the speed of E
+fn easy_speed() -> Speed {
+    get_pace("E")
+        .and_then(|pace| pace_range(split_target(&pace).0?, get_units()))
+        .map_or(Speed(f32::INFINITY), |(_, high)| Speed(high.0 + 1e-3))
+}
+
+// steps faster than E, and repetitions and ladders with such steps
+fn is_quality(w: &RunPart) -> bool {
+    match w {
+        RunPart::Step { speed, .. } => *speed > easy_speed(),
+        RunPart::Workout {
+            nodes, recovery, ..
+        } => nodes.iter().chain(recovery.as_deref()).any(is_quality),
+    }
+}
+
+/// Factor that scales the total distance of `workouts` to `distance` (m). `None` if the
+/// distance can't be reached by scaling `scope`.
+pub fn scale_factor(workouts: &[RunPart], distance: f32, scope: Scope) -> Option<f32> {
+    let (easy, quality) = workouts
+        .iter()
+        .map(volumes)
+        .fold((0.0, 0.0), |(e, q), (de, dq)| (e + de, q + dq));
+    let factor = match scope {
+        Scope::All => distance / (easy + quality),
+        Scope::Easy => (distance - quality) / easy,
+        Scope::Quality => (distance - easy) / quality,
+    };
+    (factor.is_finite() && factor > 0.0).then_some(factor)
+}
+
+/// Scale all workouts of a plan (lines of a workout file) to `target`. Empty lines stay
+/// empty and definitions are kept, references are expanded. Workouts with a `?` step keep
+/// it, their target is scaled if the `?` step is in `scope`.
+pub fn scale_plan(lines: &[&str], target: Target, scope: Scope) -> Result<Vec<String>, ParseError> {
+    let expanded = expand_lines(lines.iter().copied());
+    let mut workouts = Vec::new();
+    for line in expanded.iter().flatten() {
+        let line = line.clone()?;
+        if !line.trim().is_empty() {
+            workouts.push(parse::solve(&line)?);
+        }
+    }
+    let solved: Vec<RunPart> = workouts.iter().map(|(w, _)| w.clone()).collect();
+    let factor = match target {
+        Target::Percent(p) => p / 100.0,
+        Target::Distance(d) => scale_factor(&solved, d, scope).ok_or(ParseError {
+            span: 0..0,
+            message: format!(
+                "{} {} can't be reached",
+                get_units().distance(d),
+                get_units().label()
+            ),
+        })?,
+    };
+    let mut scaled = Vec::new();
+    let mut workouts = workouts.iter();
+    for (line, expanded) in lines.iter().zip(expanded.iter()) {
+        match expanded {
+            Some(Ok(w)) if !w.trim().is_empty() => {
+                let (_, unknown) = workouts.next().unwrap();
+                scaled.push(scale_line(w, unknown.as_ref(), factor, scope)?);
+            }
+            _ => scaled.push(line.to_string()),
+        }
+    }
+    Ok(scaled)
+}
+
+// scaled text of a workout, `unknown` is its solved `?` step if it has a target
+fn scale_line(
+    line: &str,
+    unknown: Option<&RunPart>,
+    factor: f32,
+    scope: Scope,
+) -> Result<String, ParseError> {
+    let (w, target) = parse::parse_unsolved(line)?;
+    let mut formatted = format_workout(&scale(&w, factor, scope));
+    if let (Some((rtype, target)), Some(unknown)) = (target, unknown) {
+        let target = match (in_scope(unknown, scope), &rtype) {
+            (false, _) => target,
+            (true, RunType::Time) => round(target * factor, 60.0),
+            (true, RunType::Distance) => round(target * factor, get_units().length() / 10.0),
+        };
+        formatted.push_str(&format_target(&rtype, target));
+        // the scaled target must still be reachable
+        parse::solve(&formatted).map_err(|e| ParseError {
+            span: 0..line.len(),
+            message: format!("{}: {}", formatted, e.message),
+        })?;
+    }
+    Ok(formatted)
+}
+
+// multiple of `unit`, at least one
+fn round(x: f32, unit: f32) -> f32 {
+    ((x / unit).round() * unit).max(unit)
+}
+
+// easy and quality distance (m) of a workout
+fn volumes(w: &RunPart) -> (f32, f32) {
+    match w {
+        RunPart::Workout {
+            reps: 1,
+            nodes,
+            recovery: None,
+            ladder: false,
+        } => nodes
+            .iter()
+            .map(volumes)
+            .fold((0.0, 0.0), |(e, q), (de, dq)| (e + de, q + dq)),
+        _ if is_quality(w) => (0.0, w.calc_distance()),
+        _ => (w.calc_distance(), 0.0),
+    }
+}
+
+// scale the length of a step, distances are rounded to `distance_unit` (m) and times to
+// `time_unit` (s)
+fn resize(step: &RunPart, factor: f32, distance_unit: f32, time_unit: f32) -> RunPart {
+    match step {
+        RunPart::Step {
+            rtype,
+            intensity,
+            speed,
+            end_speed,
+            speed_range,
+            hr,
+            time,
+            distance,
+        } if !time.0.is_nan() => {
+            let resized = match (rtype, end_speed) {
+                (RunType::Distance, None) => RunPart::part_from_distance(
+                    round(*distance * factor, distance_unit),
+                    *speed,
+                    intensity,
+                ),
+                (RunType::Time, None) => {
+                    let time = Duration(round(time.0 * factor, time_unit));
+                    RunPart::part_from_time(time, *speed, intensity)
+                }
+                (RunType::Distance, Some(end)) => RunPart::ramp_from_distance(
+                    round(*distance * factor, distance_unit),
+                    *speed,
+                    *end,
+                    intensity,
+                ),
+                (RunType::Time, Some(end)) => RunPart::ramp_from_time(
+                    Duration(round(time.0 * factor, time_unit)),
+                    *speed,
+                    *end,
+                    intensity,
+                ),
+            };
+            let resized = resized.with_hr(*hr);
+            match speed_range {
+                Some(range) => resized.with_range(*range),
+                None => resized,
+            }
+        }
+        _ => step.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::canonicalize;
+    use approx::assert_abs_diff_eq;
+
+    fn scaled(input: &str, factor: f32, scope: Scope) -> String {
+        format_workout(&scale(&parse::parse(input).unwrap(), factor, scope))
+    }
+
+    #[test]
+    fn scale_workout() {
+        let w = "3.2 E + 5 * (1 I + 2 min jg) + 30 min E + 3.2 E";
+        assert_eq!(
+            scaled(w, 0.85, Scope::All),
+            "2.7 E + 4 * (1 I + 2 min jg) + 26 min E + 2.7 E"
+        );
+        assert_eq!(
+            scaled(w, 1.1, Scope::Easy),
+            "3.5 E + 5 * (1 I + 2 min jg) + 33 min E + 3.5 E"
+        );
+        assert_eq!(
+            scaled(w, 1.1, Scope::Quality),
+            "3.2 E + 6 * (1 I + 2 min jg) + 30 min E + 3.2 E"
+        );
+        // at least one rep
+        assert_eq!(scaled("2 * (1 I)", 0.1, Scope::All), "1 * (1 I)");
+
+        // quality by intensity, not by nesting
+        let w = "2 E + 20 min T + 2 E";
+        assert_eq!(scaled(w, 1.1, Scope::Easy), "2.2 E + 20 min T + 2.2 E");
+        assert_eq!(scaled(w, 1.1, Scope::Quality), "2 E + 22 min T + 2 E");
+        assert_eq!(scaled("2 * (10 min E)", 1.1, Scope::Easy), "2 * (11 min E)");
+    }
+
+    #[test]
+    fn scale_ladder() {
+        let w = "2 E + ladder(400,800,1200) I w/ 2 min jg";
+        assert_eq!(
+            scaled(w, 0.85, Scope::All),
+            "1.7 E + ladder(300, 700, 1000) I w/ 105 s jg"
+        );
+        assert_eq!(
+            scaled(w, 0.85, Scope::Quality),
+            "2 E + ladder(300, 700, 1000) I w/ 2 min jg"
+        );
+        assert_eq!(
+            scaled(w, 0.85, Scope::Easy),
+            "1.7 E + ladder(400, 800, 1200) I w/ 2 min jg"
+        );
+    }
+
+    #[test]
+    fn scale_target() {
+        let lines = ["? E + 5 * (1 I) = 60 min", "10 E"];
+        let scaled = scale_plan(&lines, Target::Percent(80.0), Scope::All).unwrap();
+        assert_eq!(scaled, ["? E + 4 * (1 I) = 48 min", "8 E"]);
+        let scaled = scale_plan(&lines, Target::Percent(80.0), Scope::Quality).unwrap();
+        assert_eq!(scaled, ["? E + 4 * (1 I) = 60 min", "10 E"]);
+        // the `?` step must remain
+        let lines = ["? E + 5 * (2 I) = 45 min"];
+        assert!(scale_plan(&lines, Target::Percent(50.0), Scope::Easy).is_err());
+    }
+
+    #[test]
+    fn scale_to_distance() {
+        let workouts = [parse::parse("10 E + 5 * (1 I)").unwrap()];
+        assert_abs_diff_eq!(scale_factor(&workouts, 12000.0, Scope::All).unwrap(), 0.8);
+        assert_abs_diff_eq!(scale_factor(&workouts, 12000.0, Scope::Easy).unwrap(), 0.7);
+        assert_eq!(scale_factor(&workouts, 4000.0, Scope::Easy), None);
+
+        let lines = ["let WU = 2E", "WU + 5 * (1 I)", "", "10 E"];
+        let scaled = scale_plan(&lines, Target::Distance(8500.0), Scope::All).unwrap();
+        assert_eq!(scaled, ["let WU = 2E", "1 E + 3 * (1 I)", "", "5 E"]);
+        for line in scaled.iter().skip(1) {
+            canonicalize(line).unwrap();
+        }
+    }
+}
@@ -0,0 +1,158 @@
+// Workout, paces and aliases encoded in the fragment of a URL, e.g.
+//
+//     #w=2E_+_8*(400R_+_90s_jog)&p=E:5:30,R:3:45,jg:6:30&a=jog:jg
+//
+// The aliases ("&a=") are left out if there are none. Workouts and paces in imperial
+// units add "&u=imperial". Spaces are written as "_", all other characters that are not
+// URL safe are percent encoded.
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::utils::Units;
+
+// workout, paces, aliases and units of a fragment
+type Fragment = (
+    Option<String>,
+    Option<HashMap<String, String>>,
+    Option<HashMap<String, String>>,
+    Units,
+);
+
+pub fn encode_fragment(
+    workout: &str,
+    paces: &BTreeMap<String, String>,
+    aliases: &BTreeMap<String, String>,
+    units: Units,
+) -> String {
+    let mut fragment = format!("w={}&p={}", escape(workout), encode_map(paces));
+    if !aliases.is_empty() {
+        fragment += &format!("&a={}", encode_map(aliases));
+    }
+    match units {
+        Units::Metric => fragment,
+        Units::Imperial => fragment + "&u=imperial",
+    }
+}
+
+/// Decode a URL fragment (without the leading "#"), missing or invalid parts are `None`.
+/// The units are metric unless given.
+pub fn decode_fragment(fragment: &str) -> Fragment {
+    let mut workout = None;
+    let mut paces = None;
+    let mut aliases = None;
+    let mut units = Units::Metric;
+    for (key, value) in fragment.split('&').filter_map(|kv| kv.split_once('=')) {
+        match key {
+            "w" => workout = unescape(value),
+            "p" => paces = decode_map(value),
+            "a" => aliases = decode_map(value),
+            "u" if value == "imperial" => units = Units::Imperial,
+            _ => (),
+        }
+    }
+    (workout, paces, aliases, units)
+}
+
+// "k:v,k:v"
+fn encode_map(map: &BTreeMap<String, String>) -> String {
+    let pairs: Vec<String> = map
+        .iter()
+        .map(|(k, v)| format!("{}:{}", escape(k), escape(v)))
+        .collect();
+    pairs.join(",")
+}
+
+fn decode_map(value: &str) -> Option<HashMap<String, String>> {
+    value
+        .split(',')
+        .filter(|p| !p.is_empty())
+        .map(|p| {
+            let (k, v) = p.split_once(':')?;
+            Some((unescape(k)?, unescape(v)?))
+        })
+        .collect()
+}
+
+fn escape(input: &str) -> String {
+    let mut out = String::new();
+    for b in input.bytes() {
+        match b {
+            b' ' => out.push('_'),
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'.' | b'~' => out.push(b as char),
+            b'*' | b'(' | b')' | b'+' | b':' => out.push(b as char),
+            _ => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
+
+fn unescape(input: &str) -> Option<String> {
+    let mut bytes = Vec::new();
+    let mut iter = input.bytes();
+    while let Some(b) = iter.next() {
+        match b {
+            b'_' => bytes.push(b' '),
+            b'%' => {
+                let hex = [iter.next()?, iter.next()?];
+                bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+            }
+            _ => bytes.push(b),
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_workout() {
+        let paces = BTreeMap::from([
+            ("E".to_string(), "5:30".to_string()),
+            ("rst".to_string(), "10:00".to_string()),
+        ]);
+        let none = BTreeMap::new();
+        assert_eq!(
+            encode_fragment("2E + 8 * (400 R + 90s rst)", &paces, &none, Units::Metric),
+            "w=2E_+_8_*_(400_R_+_90s_rst)&p=E:5:30,rst:10:00"
+        );
+        let aliases = BTreeMap::from([("rest".to_string(), "rst".to_string())]);
+        assert_eq!(
+            encode_fragment("2E", &paces, &aliases, Units::Imperial),
+            "w=2E&p=E:5:30,rst:10:00&a=rest:rst&u=imperial"
+        );
+    }
+
+    #[test]
+    fn roundtrip() {
+        let paces = BTreeMap::from([
+            ("E_asy".to_string(), "5:30".to_string()),
+            ("Tempo & more".to_string(), "4:20".to_string()),
+        ]);
+        let aliases = BTreeMap::from([
+            ("easy".to_string(), "E_asy".to_string()),
+            ("T & more".to_string(), "Tempo & more".to_string()),
+        ]);
+        let workout = "3 easy + 20 min T & more\n10 min E_asy";
+        let fragment = encode_fragment(workout, &paces, &aliases, Units::Imperial);
+        let (w, p, a, u) = decode_fragment(&fragment);
+        assert_eq!(w.unwrap(), workout);
+        assert_eq!(p.unwrap(), paces.into_iter().collect());
+        assert_eq!(a.unwrap(), aliases.into_iter().collect());
+        assert_eq!(u, Units::Imperial);
+    }
+
+    #[test]
+    fn invalid_fragments() {
+        let metric = Units::Metric;
+        assert_eq!(decode_fragment(""), (None, None, None, metric));
+        assert_eq!(decode_fragment("w=%4"), (None, None, None, metric));
+        assert_eq!(decode_fragment("p=E"), (None, None, None, metric));
+        assert_eq!(decode_fragment("a=jog"), (None, None, None, metric));
+        assert_eq!(
+            decode_fragment("w=3E&x=1&u=x"),
+            (Some("3E".to_string()), None, None, metric)
+        );
+    }
+}
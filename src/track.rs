@@ -0,0 +1,138 @@
+// Track report of a workout, the laps and lap and half lap times of every distance step:
+//
+//                laps   400 m   200 m
+//     5 * (
+//       800 I    2      1:34    0:47
+//       2 min jg
+//     )
+//
+// Laps in outer lanes are longer, each lane adds a circle with the lane width (1.22 m)
+// to the lap, e.g. 407.7 m in lane 2 of a 400 m track. Laps and times are for the lap of
+// the lane.
+
+use crate::compare::{render, walk, Rows};
+use crate::parse::{self, ParseError};
+use crate::utils::{Duration, Speed};
+use crate::wtree::RunType;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+
+/// Width of a lane in m.
+pub const LANE_WIDTH: f32 = 1.22;
+
+/// Track length in m and the lane (1 is the inside lane) of a track session.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Track {
+    pub length: f32,
+    pub lane: u8,
+}
+
+impl Default for Track {
+    fn default() -> Self {
+        Track {
+            length: 400.0,
+            lane: 1,
+        }
+    }
+}
+
+impl Track {
+    /// Length of a lap in the lane.
+    pub fn lap(&self) -> f32 {
+        self.length + 2.0 * PI * LANE_WIDTH * (self.lane.max(1) - 1) as f32
+    }
+}
+
+// lap count, whole laps without decimals
+fn format_laps(laps: f32) -> String {
+    trim_decimals(laps, 2)
+}
+
+// lap length in m, e.g. 407.7 in lane 2
+fn format_length(length: f32) -> String {
+    trim_decimals(length, 1)
+}
+
+fn trim_decimals(x: f32, decimals: usize) -> String {
+    let x = format!("{:.*}", decimals, x);
+    x.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+// time of `distance` at the start and end speed of a progression
+fn split(distance: f32, speed: Speed, ramp: Option<(Speed, Speed)>) -> String {
+    match ramp {
+        Some((start, end)) => format!(
+            "{}->{}",
+            Duration(distance / start.0),
+            Duration(distance / end.0)
+        ),
+        None => Duration(distance / speed.0).to_string(),
+    }
+}
+
+/// Track table of `workout`: laps, lap and half lap time of every distance step. The
+/// header has the lap length of the lane.
+pub fn track(workout: &str, settings: Track) -> Result<String, ParseError> {
+    let w = parse::parse(workout)?;
+    let lap = settings.lap();
+    let header = vec![
+        "laps".to_string(),
+        format!("{} m", format_length(lap)),
+        format!("{} m", format_length(lap / 2.0)),
+    ];
+    let mut rows: Rows = vec![(String::new(), header)];
+    walk(&[w], &mut rows, &|indent, label, steps, rows| {
+        let step = &steps[0];
+        let cells = match step.rtype {
+            RunType::Distance => vec![
+                format_laps(step.distance / lap),
+                split(lap, step.speed, step.ramp),
+                split(lap / 2.0, step.speed, step.ramp),
+            ],
+            _ => vec![],
+        };
+        rows.push((indent.to_string() + &label, cells));
+    });
+    Ok(render(&rows))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn track_table() {
+        let table = track("5 * (800 I + 2 min jg)", Track::default()).unwrap();
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "             laps   400 m   200 m",
+                "5 * (",
+                "  800 I      2      1:36    0:48",
+                "  2 min jg",
+                ")",
+            ]
+        );
+
+        // longer laps in lane 2, long reps have fractional lap counts
+        let lane2 = Track {
+            length: 400.0,
+            lane: 2,
+        };
+        assert!((lane2.lap() - 407.67).abs() < 0.01);
+        let table = track("1 T", lane2).unwrap();
+        assert_eq!(
+            table,
+            "      laps   407.7 m   203.8 m\n1 T   2.45   1:50      0:55\n"
+        );
+
+        let indoor = Track {
+            length: 200.0,
+            lane: 1,
+        };
+        let table = track("1000 R", indoor).unwrap();
+        assert!(table.ends_with("1 R   5      0:42    0:21\n"), "{}", table);
+    }
+}
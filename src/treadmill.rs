@@ -0,0 +1,158 @@
+// Treadmill settings of a workout, the belt speed of every step rounded to the
+// treadmill's increment:
+//
+//                   speed       incline   time
+//     10 E          10.6 km/h   1%        56:36
+//     3 * (
+//       2 min I     15.3 km/h   1%        2:00
+//       2 min rst   4.0 km/h    1%        2:00
+//     )
+//
+// A 1% incline makes up for the missing air resistance (Jones & Doust, 1996). Times of
+// distance based steps are calculated with the rounded belt speed.
+
+use crate::compare::{render, walk, Rows};
+use crate::config::get_units;
+use crate::parse::{self, ParseError};
+use crate::utils::{Duration, Speed, Units};
+use crate::wtree::RunType;
+use serde::{Deserialize, Serialize};
+
+/// Settings of the treadmill, the increment is in km/h or mph of the unit system.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Treadmill {
+    pub increment: f32,
+    pub incline: bool,
+}
+
+impl Default for Treadmill {
+    fn default() -> Self {
+        Treadmill {
+            increment: 0.1,
+            incline: false,
+        }
+    }
+}
+
+impl Treadmill {
+    /// Belt speed in km/h or mph of a speed, rounded to the increment (if any). Speeds
+    /// below the increment run at one increment rather than a standing belt.
+    pub fn belt_speed(&self, speed: Speed) -> f32 {
+        let per_hour = speed.0 * 3600.0 / get_units().length();
+        if self.increment > 0.0 && per_hour > 0.0 {
+            ((per_hour / self.increment).round() * self.increment).max(self.increment)
+        } else {
+            per_hour
+        }
+    }
+
+    // belt speed with as many decimals as the increment has (at least one without one)
+    fn format(&self, speed: Speed) -> String {
+        let decimals = if self.increment > 0.0 {
+            let increment = self.increment.to_string();
+            increment
+                .split_once('.')
+                .map_or(0, |(_, fract)| fract.len().min(3))
+        } else {
+            1
+        };
+        format!("{:.*}", decimals, self.belt_speed(speed))
+    }
+}
+
+/// Treadmill table of `workout`: belt speed, incline and time of every step.
+pub fn treadmill(workout: &str, settings: Treadmill) -> Result<String, ParseError> {
+    let w = parse::parse(workout)?;
+    let unit = match get_units() {
+        Units::Metric => "km/h",
+        Units::Imperial => "mph",
+    };
+    let mut header = vec!["speed".to_string(), "time".to_string()];
+    if settings.incline {
+        header.insert(1, "incline".to_string());
+    }
+    let mut rows: Rows = vec![(String::new(), header)];
+    walk(&[w], &mut rows, &|indent, label, steps, rows| {
+        let step = &steps[0];
+        let speed = match step.ramp {
+            Some((start, end)) => {
+                format!(
+                    "{}->{} {}",
+                    settings.format(start),
+                    settings.format(end),
+                    unit
+                )
+            }
+            // heart rate only steps have no speed
+            None if step.speed == Speed(0.0) => step.pace.clone(),
+            None => format!("{} {}", settings.format(step.speed), unit),
+        };
+        let time = match (&step.rtype, step.ramp) {
+            (RunType::Distance, None) => {
+                let belt = settings.belt_speed(step.speed) * get_units().length() / 3600.0;
+                Duration(step.distance / belt)
+            }
+            _ => step.time,
+        };
+        let mut cells = vec![speed, time.to_string()];
+        if settings.incline {
+            cells.insert(1, "1%".to_string());
+        }
+        rows.push((indent.to_string() + &label, cells));
+    });
+    Ok(render(&rows))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::init_units;
+
+    #[test]
+    fn treadmill_table() {
+        let table = treadmill("2 E + 3 * (1 I) w/ 2 min jg", Treadmill::default()).unwrap();
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "                speed       time",
+                "2 E             10.0 km/h   12:00",
+                "3 * (",
+                "  1 I           15.0 km/h   4:00",
+                "  w/ 2 min jg   7.5 km/h    2:00",
+                ")",
+            ]
+        );
+
+        // the time of distance steps follows the rounded speed
+        let settings = Treadmill {
+            increment: 0.5,
+            incline: true,
+        };
+        let table = treadmill("1 T", settings).unwrap();
+        assert_eq!(
+            table,
+            "      speed       incline   time\n1 T   13.5 km/h   1%        4:27\n"
+        );
+
+        // quarter increments, slow steps run at one increment
+        let settings = Treadmill {
+            increment: 0.25,
+            incline: false,
+        };
+        let table = treadmill("1 T", settings).unwrap();
+        assert!(table.contains("1 T   13.25 km/h"), "{}", table);
+        let settings = Treadmill {
+            increment: 10.0,
+            incline: false,
+        };
+        let table = treadmill("2 min rst", settings).unwrap();
+        assert!(table.contains("2 min rst   10 km/h"), "{}", table);
+
+        init_units(Units::Imperial);
+        let table = treadmill("10 min E", Treadmill::default()).unwrap();
+        assert!(table.contains("10 min E   10.0 mph"), "{}", table);
+        init_units(Units::Metric);
+    }
+}
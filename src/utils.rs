@@ -1,22 +1,321 @@
-pub fn pace2speed(pace: &str) -> Option<f32> {
-    // pace is min:sec per kilometer, speed is m/s
-    let values: Vec<_> = pace.split(':').collect();
-    if values.len() != 2 {
+// Value types for speeds, paces and durations. They parse, validate and format the paces
+// of the config and the speeds and times of workouts (`RunPart`) and outputs. They are
+// serialized as plain numbers (m/s and s), so the JSON schema of workouts is unchanged.
+// Formatting rounds to whole seconds (or minutes for `Duration::hours_minutes`), never
+// truncates.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+const METERS_PER_MILE: f32 = 1609.344;
+
+/// Speed in m/s.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Speed(pub f32);
+
+/// Pace in seconds per kilometer.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Pace(pub f32);
+
+/// Duration in seconds.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Duration(pub f32);
+
+/// How a speed is written.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PaceUnit {
+    MinPerKm,
+    MinPerMile,
+    Kmh,
+    Mph,
+}
+
+impl PaceUnit {
+    pub fn label(&self) -> &'static str {
+        match self {
+            PaceUnit::MinPerKm => "min/km",
+            PaceUnit::MinPerMile => "min/mi",
+            PaceUnit::Kmh => "km/h",
+            PaceUnit::Mph => "mph",
+        }
+    }
+}
+
+/// Unit system of workout inputs and all outputs, the `units` key of `paces.toml`.
+/// Workouts are calculated in metres and seconds either way.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Units {
+    #[default]
+    Metric,
+    Imperial,
+}
+
+impl Units {
+    pub fn is_metric(&self) -> bool {
+        *self == Units::Metric
+    }
+
+    /// Metres of a km or mile, the unit of distances without unit.
+    pub fn length(&self) -> f32 {
+        match self {
+            Units::Metric => 1000.0,
+            Units::Imperial => METERS_PER_MILE,
+        }
+    }
+
+    /// "km" or "mi".
+    pub fn label(&self) -> &'static str {
+        match self {
+            Units::Metric => "km",
+            Units::Imperial => "mi",
+        }
+    }
+
+    pub fn pace_unit(&self) -> PaceUnit {
+        match self {
+            Units::Metric => PaceUnit::MinPerKm,
+            Units::Imperial => PaceUnit::MinPerMile,
+        }
+    }
+
+    /// Distance (m) in km or miles with one decimal, without the unit label.
+    pub fn distance(&self, distance: f32) -> String {
+        format!("{:.1}", distance / self.length())
+    }
+
+    /// Pace of a speed (m/s) per km or mile, "-" without a speed, e.g. the average of
+    /// heart rate only steps.
+    pub fn pace(&self, speed: Speed) -> String {
+        if speed.0 > 0.0 && speed.0.is_finite() {
+            speed.format(self.pace_unit())
+        } else {
+            "-".to_string()
+        }
+    }
+}
+
+impl Speed {
+    /// The speed in `unit`, without the unit label, e.g. "4:05" or "14.7".
+    pub fn format(&self, unit: PaceUnit) -> String {
+        match unit {
+            PaceUnit::MinPerKm => Pace::from(*self).to_string(),
+            PaceUnit::MinPerMile => Duration(METERS_PER_MILE / self.0).to_string(),
+            PaceUnit::Kmh => format!("{:.1}", self.0 * 3.6),
+            PaceUnit::Mph => format!("{:.1}", self.0 * 3600.0 / METERS_PER_MILE),
+        }
+    }
+}
+
+impl From<Pace> for Speed {
+    fn from(pace: Pace) -> Speed {
+        Speed(1000.0 / pace.0)
+    }
+}
+
+impl From<Speed> for Pace {
+    fn from(speed: Speed) -> Pace {
+        Pace(1000.0 / speed.0)
+    }
+}
+
+/// A pace "m:ss" per kilometer (optionally "m:ss/km"), "m:ss/mi" per mile, or a speed
+/// "14.5 km/h" or "9 mph". Seconds may have decimals ("4:05.5"), slow paces hours
+/// ("1:02:30").
+impl FromStr for Speed {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Speed, String> {
+        Speed::parse(s, PaceUnit::MinPerKm)
+    }
+}
+
+impl Speed {
+    /// Like `from_str`, but a pace without unit is per km or per mile as given by
+    /// `default`.
+    pub fn parse(s: &str, default: PaceUnit) -> Result<Speed, String> {
+        let s = s.trim();
+        let invalid = || format!("invalid pace \"{}\"", s);
+        let speed = if let Some(kmh) = s.strip_suffix("km/h") {
+            kmh.trim().parse::<f32>().map_err(|_| invalid())? / 3.6
+        } else if let Some(mph) = s.strip_suffix("mph") {
+            mph.trim().parse::<f32>().map_err(|_| invalid())? * METERS_PER_MILE / 3600.0
+        } else if let Some(pace) = s.strip_suffix("/mi") {
+            METERS_PER_MILE / parse_clock(pace).ok_or_else(invalid)?
+        } else if let Some(pace) = s.strip_suffix("/km") {
+            1000.0 / parse_clock(pace).ok_or_else(invalid)?
+        } else if default == PaceUnit::MinPerMile {
+            METERS_PER_MILE / parse_clock(s).ok_or_else(invalid)?
+        } else {
+            1000.0 / parse_clock(s).ok_or_else(invalid)?
+        };
+        if speed.is_finite() && speed > 0.0 {
+            Ok(Speed(speed))
+        } else {
+            Err(invalid())
+        }
+    }
+}
+
+impl FromStr for Pace {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Pace, String> {
+        s.parse::<Speed>().map(Pace::from)
+    }
+}
+
+/// "m:ss" or "h:mm:ss", seconds may have decimals.
+impl FromStr for Duration {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Duration, String> {
+        parse_clock(s.trim())
+            .map(Duration)
+            .ok_or(format!("invalid duration \"{}\"", s.trim()))
+    }
+}
+
+impl fmt::Display for Pace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Duration(self.0).fmt(f)
+    }
+}
+
+impl Duration {
+    /// Rounded to minutes, "h:mm".
+    pub fn hours_minutes(&self) -> String {
+        let minutes = (self.0 / 60.0).round() as i64;
+        format!("{}:{:02}", minutes / 60, minutes % 60)
+    }
+}
+
+/// Rounded to seconds, "m:ss" or "h:mm:ss" from one hour on.
+impl fmt::Display for Duration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let seconds = self.0.round() as i64;
+        let (h, m, s) = (seconds / 3600, seconds % 3600 / 60, seconds % 60);
+        if h > 0 {
+            write!(f, "{}:{:02}:{:02}", h, m, s)
+        } else {
+            write!(f, "{}:{:02}", m, s)
+        }
+    }
+}
+
+// seconds of "m:ss" or "h:mm:ss", all but the first field have two digits and are below
+// 60. The result is positive.
+fn parse_clock(s: &str) -> Option<f32> {
+    let fields: Vec<&str> = s.split(':').collect();
+    if fields.len() < 2 || fields.len() > 3 {
         return None;
     }
-    let minutes = values[0].parse::<i32>();
-    let seconds = values[1].parse::<i32>();
-    match (minutes, seconds) {
-        (Ok(min), Ok(sec)) => Some(1000.0 / (min * 60 + sec) as f32),
-        _ => None,
+    let digits = |f: &str| !f.is_empty() && f.chars().all(|c| c.is_ascii_digit());
+    let mut total = 0.0;
+    for (i, field) in fields.iter().enumerate() {
+        let value = if i + 1 == fields.len() {
+            // seconds, "05" or "05.5"
+            let (whole, fraction) = field.split_once('.').unwrap_or((field, "0"));
+            if whole.len() != 2 || !digits(whole) || !digits(fraction) {
+                return None;
+            }
+            field.parse::<f32>().ok()?
+        } else {
+            if !digits(field) || (i > 0 && field.len() != 2) {
+                return None;
+            }
+            field.parse::<f32>().ok()?
+        };
+        if i > 0 && value >= 60.0 {
+            return None;
+        }
+        total = total * 60.0 + value;
     }
+    (total > 0.0).then_some(total)
 }
 
-pub fn speed2pace(speed: f32) -> String {
-    let seconds = (1000.0 / speed) as i32;
-    let mins = seconds / 60;
-    let remaining = seconds % 60_i32;
-    format!("{}:{:02}", mins, remaining)
+/// Low and high speed of a pace range, e.g. "4:10-4:20". A single pace is a range with
+/// both speeds equal. A unit after the range applies to both ends ("14-15 km/h",
+/// "8:00-8:30/mi"). Paces without unit are per km or mile of `units`.
+pub fn pace_range(pace: &str, units: Units) -> Option<(Speed, Speed)> {
+    const UNITS: [&str; 4] = ["km/h", "mph", "/mi", "/km"];
+    let unit = |p: &str| UNITS.into_iter().find(|u| p.trim_end().ends_with(u));
+    let speed = |p: &str| Speed::parse(p, units.pace_unit()).ok();
+    let (a, b) = match pace.split_once('-') {
+        Some((a, b)) => match (unit(a), unit(b)) {
+            (None, Some(u)) => (speed(&format!("{}{}", a.trim_end(), u))?, speed(b)?),
+            _ => (speed(a)?, speed(b)?),
+        },
+        None => (speed(pace)?, speed(pace)?),
+    };
+    Some(if a <= b { (a, b) } else { (b, a) })
+}
+
+/// Speed of the pace in the middle of a pace range.
+pub fn mid_speed((low, high): (Speed, Speed)) -> Speed {
+    Speed(2.0 * low.0 * high.0 / (low.0 + high.0))
+}
+
+/// Pace range of a speed range, fastest pace first. A single pace if both are the same.
+pub fn range2pace(low: Speed, high: Speed, units: Units) -> String {
+    let (fast, slow) = (units.pace(high), units.pace(low));
+    if fast == slow {
+        fast
+    } else {
+        format!("{}-{}", fast, slow)
+    }
+}
+
+/// Pace and heart rate parts of an intensity, e.g. "5:30 @ 130-145 bpm". Heart rate only
+/// intensities like "65-75% max" have no pace.
+pub fn split_target(value: &str) -> (Option<&str>, Option<&str>) {
+    let is_heart_rate = ["bpm", "max", "lthr"]
+        .iter()
+        .any(|unit| value.trim_end().ends_with(unit));
+    match value.split_once('@') {
+        Some((pace, hr)) => (Some(pace.trim()), Some(hr.trim())),
+        None if is_heart_rate => (None, Some(value.trim())),
+        None => (Some(value.trim()), None),
+    }
+}
+
+/// Heart rate range (bpm) of "130-145 bpm" or of a percentage of the maximum or lactate
+/// threshold heart rate, "65-75% max" or "85-89% lthr". Percentages need that heart rate.
+pub fn hr_range(hr: &str, max_hr: Option<u32>, lthr: Option<u32>) -> Option<(u32, u32)> {
+    let hr = hr.trim();
+    let (values, base) = if let Some(v) = hr.strip_suffix("bpm") {
+        (v, None)
+    } else if let Some(v) = hr.strip_suffix("max") {
+        (v.trim_end().strip_suffix('%')?, Some(max_hr?))
+    } else if let Some(v) = hr.strip_suffix("lthr") {
+        (v.trim_end().strip_suffix('%')?, Some(lthr?))
+    } else {
+        return None;
+    };
+    let bpm = |v: &str| {
+        let v: f32 = v.trim().parse().ok()?;
+        let bpm = base.map_or(v, |base| v * base as f32 / 100.0);
+        (v > 0.0 && bpm < 250.0).then_some(bpm.round() as u32)
+    };
+    let (a, b) = match values.split_once('-') {
+        Some((a, b)) => (bpm(a)?, bpm(b)?),
+        None => (bpm(values)?, bpm(values)?),
+    };
+    Some((a.min(b), a.max(b)))
+}
+
+/// Heart rate range, e.g. "130-145 bpm". A single value if both are the same.
+pub fn format_hr((low, high): (u32, u32)) -> String {
+    if low == high {
+        format!("{} bpm", low)
+    } else {
+        format!("{}-{} bpm", low, high)
+    }
 }
 
 #[cfg(test)]
@@ -24,16 +323,114 @@ mod tests {
     use super::*;
     use approx::assert_abs_diff_eq;
 
+    #[test]
+    fn heart_rates() {
+        assert_eq!(split_target("5:30"), (Some("5:30"), None));
+        assert_eq!(
+            split_target("5:30 @ 130-145 bpm"),
+            (Some("5:30"), Some("130-145 bpm"))
+        );
+        assert_eq!(split_target("65-75% max"), (None, Some("65-75% max")));
+        assert_eq!(hr_range("130-145 bpm", None, None), Some((130, 145)));
+        assert_eq!(hr_range("150bpm", None, None), Some((150, 150)));
+        assert_eq!(hr_range("65-75% max", Some(190), None), Some((124, 143)));
+        assert_eq!(
+            hr_range("85 - 89 % lthr", None, Some(170)),
+            Some((145, 151))
+        );
+        assert_eq!(hr_range("65-75% max", None, Some(170)), None);
+        assert_eq!(hr_range("130-145", None, None), None);
+        assert_eq!(hr_range("0-145 bpm", None, None), None);
+        assert_eq!(hr_range("300 bpm", None, None), None);
+        assert_eq!(format_hr((130, 145)), "130-145 bpm");
+        assert_eq!(format_hr((150, 150)), "150 bpm");
+    }
+
     #[test]
     fn pace_speed_convert() {
-        assert_abs_diff_eq!(pace2speed("6:00").unwrap(), 10.0 / 3.6, epsilon = 0.1);
-        assert_eq!(speed2pace(2.778), "5:59");
+        let speed = |s: &str| s.parse::<Speed>().unwrap().0;
+        assert_abs_diff_eq!(speed("6:00"), 10.0 / 3.6, epsilon = 0.1);
+        assert_eq!(Pace::from(Speed(2.778)).to_string(), "6:00");
+        assert_eq!(Pace::from(Speed(1000.0 / 899.6)).to_string(), "15:00");
+        assert_eq!("6:00".parse::<Pace>().unwrap(), Pace(360.0));
+        assert_abs_diff_eq!(speed("4:05.5"), 1000.0 / 245.5);
+        assert_abs_diff_eq!(speed("1:02:30"), 1000.0 / 3750.0);
+        assert_abs_diff_eq!(speed("5:00/km"), 1000.0 / 300.0);
+        assert_abs_diff_eq!(speed("8:00/mi"), 1609.344 / 480.0);
+        assert_abs_diff_eq!(speed("18 km/h"), 5.0);
+        assert_abs_diff_eq!(speed("10mph"), 4.4704);
+    }
+
+    #[test]
+    fn speed_units() {
+        let speed = Speed(1000.0 / 245.0);
+        assert_eq!(speed.format(PaceUnit::MinPerKm), "4:05");
+        assert_eq!(speed.format(PaceUnit::MinPerMile), "6:34");
+        assert_eq!(speed.format(PaceUnit::Kmh), "14.7");
+        assert_eq!(speed.format(PaceUnit::Mph), "9.1");
+        assert_eq!("4:05".parse::<Pace>().unwrap(), Pace(245.0));
+
+        let mile = Speed::parse("8:00", PaceUnit::MinPerMile).unwrap();
+        assert_abs_diff_eq!(mile.0, 1609.344 / 480.0);
+        assert_eq!(Units::Imperial.pace(mile), "8:00");
+        assert_eq!(Units::Metric.pace(mile), "4:58");
+        assert_eq!(Units::Metric.pace(Speed(0.0)), "-");
+        assert_eq!(Units::Imperial.distance(16093.44), "10.0");
+        let (low, high) = pace_range("7:50-8:10/km", Units::Imperial).unwrap();
+        assert_abs_diff_eq!(low.0, 1000.0 / 490.0);
+        assert_abs_diff_eq!(high.0, 1000.0 / 470.0);
+        let (low, high) = pace_range("7:50-8:10", Units::Imperial).unwrap();
+        assert_abs_diff_eq!(low.0, 1609.344 / 490.0);
+        assert_abs_diff_eq!(high.0, 1609.344 / 470.0);
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!("1:02:30".parse::<Duration>().unwrap(), Duration(3750.0));
+        assert_eq!(Duration(3750.0).to_string(), "1:02:30");
+        assert_eq!(Duration(359.6).to_string(), "6:00");
+        assert_eq!(Duration(5369.0).hours_minutes(), "1:29");
+        assert_eq!(Duration(5370.0).hours_minutes(), "1:30");
+        assert!("1:60:00".parse::<Duration>().is_err());
+    }
+
+    #[test]
+    fn pace_ranges() {
+        let (low, high) = pace_range("4:20-4:10", Units::Metric).unwrap();
+        assert_abs_diff_eq!(low.0, 1000.0 / 260.0);
+        assert_abs_diff_eq!(high.0, 1000.0 / 250.0);
+        assert_abs_diff_eq!(mid_speed((low, high)).0, 1000.0 / 255.0);
+        assert_eq!(
+            pace_range("6:00", Units::Metric),
+            Some((Speed(1000.0 / 360.0), Speed(1000.0 / 360.0)))
+        );
+        assert_eq!(pace_range("4:10-", Units::Metric), None);
+        assert_eq!(range2pace(low, high, Units::Metric), "4:10-4:20");
+        assert_eq!(range2pace(Speed(2.5), Speed(2.5), Units::Metric), "6:40");
+        let (low, high) = pace_range("14-15 km/h", Units::Metric).unwrap();
+        assert_abs_diff_eq!(low.0, 14.0 / 3.6);
+        assert_abs_diff_eq!(high.0, 15.0 / 3.6);
+        let (low, high) = pace_range("8:00-8:30/mi", Units::Metric).unwrap();
+        assert_abs_diff_eq!(low.0, METERS_PER_MILE / 510.0);
+        assert_abs_diff_eq!(high.0, METERS_PER_MILE / 480.0);
+        assert_eq!(
+            pace_range("4:10/km-8:30/mi", Units::Metric),
+            Some((Speed(METERS_PER_MILE / 510.0), Speed(1000.0 / 250.0)))
+        );
     }
 
     #[test]
     fn invalid_pace() {
-        assert_eq!(pace2speed("23:a"), None);
-        assert_eq!(pace2speed("b:02a"), None);
-        assert_eq!(pace2speed("b"), None);
+        for pace in [
+            "23:a", "b:02a", "b", "5:75", "5:7", "0:00", "0 km/h", "1:2:30", "-4:00",
+        ] {
+            assert!(pace.parse::<Speed>().is_err(), "{}", pace);
+            assert!(pace.parse::<Pace>().is_err(), "{}", pace);
+        }
+        assert_eq!(
+            "5:75".parse::<Speed>(),
+            Err("invalid pace \"5:75\"".to_string())
+        );
+        assert!("0:00".parse::<Duration>().is_err());
     }
 }
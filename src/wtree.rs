@@ -1,8 +1,13 @@
+use std::collections::BTreeMap;
 use std::fmt;
 
+use serde::{Deserialize, Serialize};
+
+use crate::config::get_units;
 use crate::utils::*;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum RunType {
     Distance,
     Time,
@@ -14,54 +19,203 @@ pub enum RunType {
 //
 // Original implementation used trait objects for Step and Workout, changed to enum
 // because we are dealing with a "closed set". Not sure if the code is really cleaner now.
-#[derive(Debug, Clone)]
+//
+// JSON schema (serde), see NOTES.md. The schema is stable: fields are never renamed or
+// removed, new fields are optional. Speeds and times are `Speed` and `Duration` values,
+// serialized as plain numbers (m/s and s) like the distances (m).
+//
+//     {"type": "workout", "reps": 2, "nodes": [
+//         {"type": "step", "rtype": "distance", "intensity": "T",
+//          "speed": 3.77, "time": 424.4, "distance": 1600.0},
+//         {"type": "step", "rtype": "time", "intensity": "rst",
+//          "speed": 1.11, "time": 60.0, "distance": 66.7}]}
+//
+// A progression step ramps its speed linearly (over time) from `speed` to the optional
+// `end_speed`:
+//
+//     {"type": "step", "rtype": "distance", "intensity": "E->M",
+//      "speed": 2.94, "end_speed": 3.61, "time": 3064.2, "distance": 10000.0}
+//
+// Intensities with a pace range ("4:10-4:20") have the speed of the middle pace and the
+// optional `speed_range`, the lowest and highest average speed of the step:
+//
+//     {"type": "step", "rtype": "distance", "intensity": "T", "speed": 3.92,
+//      "speed_range": [3.85, 4.0], "time": 2040.0, "distance": 8000.0}
+//
+// Intensities with a heart rate target have the optional `hr`, low and high bpm. Heart
+// rate only intensities have no pace, their (time based) steps have speed and distance 0:
+//
+//     {"type": "step", "rtype": "time", "intensity": "Z2", "speed": 0.0,
+//      "hr": [130, 145], "time": 1800.0, "distance": 0.0}
+//
+// A `recovery` is run between the repetitions only, not after the last one:
+//
+//     {"type": "workout", "reps": 8, "nodes": [{"type": "step", ...}],
+//      "recovery": {"type": "step", ...}}
+//
+// Ladders are a single repetition with `"ladder": true`, their nodes are the elements and
+// the `recovery` is run between the elements:
+//
+//     {"type": "workout", "reps": 1, "nodes": [<400 I>, <800 I>, <1200 I>],
+//      "recovery": <2 min jg>, "ladder": true}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
 pub enum RunPart {
     Step {
         rtype: RunType, // based on distance or time
-        speed: f32,     // m/s
-        time: f32,      // s
-        distance: f32,  // m
+        intensity: String,
+        speed: Speed,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        end_speed: Option<Speed>, // progression from speed to end_speed
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        speed_range: Option<(Speed, Speed)>, // low and high speed of pace ranges
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        hr: Option<(u32, u32)>, // bpm, heart rate target
+        time: Duration,
+        distance: f32, // m
     },
     Workout {
         reps: i32,
         nodes: Vec<RunPart>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        recovery: Option<Box<RunPart>>, // between the repetitions (ladders: elements)
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        ladder: bool, // `ladder(...)`, the nodes are its elements
     },
 }
 use RunPart::{Step, Workout};
 
 impl RunPart {
-    pub fn part_from_distance(distance: f32, speed: f32) -> RunPart {
-        let time = distance / speed;
+    pub fn part_from_distance(distance: f32, speed: Speed, intensity: &str) -> RunPart {
+        let time = Duration(distance / speed.0);
+        Step {
+            rtype: RunType::Distance,
+            intensity: intensity.to_string(),
+            speed,
+            end_speed: None,
+            speed_range: None,
+            hr: None,
+            time,
+            distance,
+        }
+    }
+
+    pub fn part_from_time(time: Duration, speed: Speed, intensity: &str) -> RunPart {
+        let distance = time.0 * speed.0;
+        Step {
+            rtype: RunType::Time,
+            intensity: intensity.to_string(),
+            speed,
+            end_speed: None,
+            speed_range: None,
+            hr: None,
+            time,
+            distance,
+        }
+    }
+
+    /// Progression over `distance` with the speed ramping linearly from `speed` to
+    /// `end_speed`, the average speed is the mean of both.
+    pub fn ramp_from_distance(
+        distance: f32,
+        speed: Speed,
+        end_speed: Speed,
+        intensity: &str,
+    ) -> RunPart {
+        let time = Duration(distance / ((speed.0 + end_speed.0) / 2.0));
         Step {
             rtype: RunType::Distance,
+            intensity: intensity.to_string(),
             speed,
+            end_speed: Some(end_speed),
+            speed_range: None,
+            hr: None,
             time,
             distance,
         }
     }
 
-    pub fn part_from_time(time: f32, speed: f32) -> RunPart {
-        let distance = time * speed;
+    /// Progression over `time`, see `ramp_from_distance`.
+    pub fn ramp_from_time(
+        time: Duration,
+        speed: Speed,
+        end_speed: Speed,
+        intensity: &str,
+    ) -> RunPart {
+        let distance = time.0 * (speed.0 + end_speed.0) / 2.0;
         Step {
             rtype: RunType::Time,
+            intensity: intensity.to_string(),
             speed,
+            end_speed: Some(end_speed),
+            speed_range: None,
+            hr: None,
             time,
             distance,
         }
     }
 
+    /// Step of unknown length (`? E`), its time and distance are NaN until it is solved.
+    pub fn part_unknown(speed: Speed, intensity: &str) -> RunPart {
+        Step {
+            rtype: RunType::Time,
+            intensity: intensity.to_string(),
+            speed,
+            end_speed: None,
+            speed_range: None,
+            hr: None,
+            time: Duration(f32::NAN),
+            distance: f32::NAN,
+        }
+    }
+
+    /// Step with the speed range `(low, high)` of a pace range, a range without width is
+    /// dropped.
+    pub fn with_range(mut self, (low, high): (Speed, Speed)) -> RunPart {
+        if let Step { speed_range, .. } = &mut self {
+            *speed_range = (low < high).then_some((low, high));
+        }
+        self
+    }
+
+    /// Step with the heart rate target `hr` (bpm).
+    pub fn with_hr(mut self, target: Option<(u32, u32)>) -> RunPart {
+        if let Step { hr, .. } = &mut self {
+            *hr = target;
+        }
+        self
+    }
+
     pub fn new_workout(reps: i32) -> RunPart {
         Workout {
             reps,
             nodes: Vec::new(),
+            recovery: None,
+            ladder: false,
+        }
+    }
+
+    /// Single repetition of the elements of a ladder, the recovery is run between them.
+    pub fn new_ladder() -> RunPart {
+        Workout {
+            reps: 1,
+            nodes: Vec::new(),
+            recovery: None,
+            ladder: true,
         }
     }
 
     pub fn calc_time(&self) -> f32 {
         match self {
-            Step { time, .. } => *time,
-            Workout { reps, nodes } => {
+            Step { time, .. } => time.0,
+            Workout {
+                reps,
+                nodes,
+                recovery,
+                ..
+            } => {
                 *reps as f32 * nodes.iter().fold(0.0, |acc, x| acc + x.calc_time())
+                    + self.recovery_runs() * recovery.as_ref().map_or(0.0, |r| r.calc_time())
             }
         }
     }
@@ -69,11 +223,288 @@ impl RunPart {
     pub fn calc_distance(&self) -> f32 {
         match self {
             Step { distance, .. } => *distance,
-            Workout { reps, nodes } => {
+            Workout {
+                reps,
+                nodes,
+                recovery,
+                ..
+            } => {
                 *reps as f32 * nodes.iter().fold(0.0, |acc, x| acc + x.calc_distance())
+                    + self.recovery_runs() * recovery.as_ref().map_or(0.0, |r| r.calc_distance())
+            }
+        }
+    }
+
+    /// Lowest and highest total time (s) within the pace ranges.
+    pub fn time_range(&self) -> (f32, f32) {
+        self.calc_range(&|step| match step {
+            Step {
+                rtype: RunType::Distance,
+                distance,
+                speed_range: Some((low, high)),
+                ..
+            } => (distance / high.0, distance / low.0),
+            _ => (step.calc_time(), step.calc_time()),
+        })
+    }
+
+    /// Lowest and highest total distance (m) within the pace ranges.
+    pub fn distance_range(&self) -> (f32, f32) {
+        self.calc_range(&|step| match step {
+            Step {
+                rtype: RunType::Time,
+                time,
+                speed_range: Some((low, high)),
+                ..
+            } => (time.0 * low.0, time.0 * high.0),
+            _ => (step.calc_distance(), step.calc_distance()),
+        })
+    }
+
+    /// Total time (s) of heart rate only steps, they have no pace and distance.
+    pub fn calc_hr_time(&self) -> f32 {
+        self.calc_range(&|step| match step {
+            Step { speed, time, .. } if *speed == Speed(0.0) => (time.0, time.0),
+            _ => (0.0, 0.0),
+        })
+        .0
+    }
+
+    // sum of the ranges of all steps, summed up like `calc_time`
+    fn calc_range(&self, step: &dyn Fn(&RunPart) -> (f32, f32)) -> (f32, f32) {
+        match self {
+            Step { .. } => step(self),
+            Workout {
+                reps,
+                nodes,
+                recovery,
+                ..
+            } => {
+                let (low, high) = nodes.iter().fold((0.0, 0.0), |acc, x| {
+                    let (l, h) = x.calc_range(step);
+                    (acc.0 + l, acc.1 + h)
+                });
+                let (rec_low, rec_high) =
+                    recovery.as_ref().map_or((0.0, 0.0), |r| r.calc_range(step));
+                (
+                    *reps as f32 * low + self.recovery_runs() * rec_low,
+                    *reps as f32 * high + self.recovery_runs() * rec_high,
+                )
+            }
+        }
+    }
+
+    /// Same structure and intensities with (almost) the same distances and times, e.g. a
+    /// workout and its re-parsed canonical text.
+    pub fn is_equivalent(&self, other: &RunPart) -> bool {
+        let close = |a: f32, b: f32| approx::relative_eq!(a, b, max_relative = 1e-4);
+        match (self, other) {
+            (
+                Step {
+                    rtype: r1,
+                    intensity: i1,
+                    time: t1,
+                    distance: d1,
+                    ..
+                },
+                Step {
+                    rtype: r2,
+                    intensity: i2,
+                    time: t2,
+                    distance: d2,
+                    ..
+                },
+            ) => r1 == r2 && i1 == i2 && close(t1.0, t2.0) && close(*d1, *d2),
+            (
+                Workout {
+                    reps: r1,
+                    nodes: n1,
+                    recovery: rec1,
+                    ladder: l1,
+                },
+                Workout {
+                    reps: r2,
+                    nodes: n2,
+                    recovery: rec2,
+                    ladder: l2,
+                },
+            ) => {
+                r1 == r2
+                    && l1 == l2
+                    && n1.len() == n2.len()
+                    && n1.iter().zip(n2.iter()).all(|(a, b)| a.is_equivalent(b))
+                    && match (rec1, rec2) {
+                        (Some(a), Some(b)) => a.is_equivalent(b),
+                        (None, None) => true,
+                        _ => false,
+                    }
+            }
+            _ => false,
+        }
+    }
+
+    /// Number of unknown steps.
+    pub fn unknowns(&self) -> usize {
+        match self {
+            Step { time, .. } => time.0.is_nan() as usize,
+            Workout {
+                nodes, recovery, ..
+            } => {
+                nodes.iter().map(RunPart::unknowns).sum::<usize>()
+                    + recovery.as_ref().map_or(0, |r| r.unknowns())
             }
         }
     }
+
+    /// Give the unknown step the length that makes the total time (s) or distance (m) of
+    /// the workout `target`. Returns the solved step.
+    pub fn solve(&mut self, rtype: RunType, target: f32) -> Result<RunPart, String> {
+        let known = self.calc_known(&rtype);
+        let (step, runs) = self.unknown_mut(1.0).ok_or("no `?` step")?;
+        if runs == 0.0 {
+            return Err("the `?` step is never run".to_string());
+        }
+        let missing = (target - known) / runs;
+        if missing <= 0.0 {
+            return Err("the target is reached without the `?` step".to_string());
+        }
+        if let Step {
+            speed,
+            intensity,
+            speed_range,
+            hr,
+            ..
+        } = step
+        {
+            let solved = match rtype {
+                RunType::Time => RunPart::part_from_time(Duration(missing), *speed, intensity),
+                RunType::Distance => RunPart::part_from_distance(missing, *speed, intensity),
+            }
+            .with_hr(*hr);
+            *step = match speed_range {
+                Some(range) => solved.with_range(*range),
+                None => solved,
+            };
+        }
+        Ok(step.clone())
+    }
+
+    // total time or distance without the unknown step
+    fn calc_known(&self, rtype: &RunType) -> f32 {
+        match self {
+            Step { time, .. } if time.0.is_nan() => 0.0,
+            Step { time, distance, .. } => match rtype {
+                RunType::Time => time.0,
+                RunType::Distance => *distance,
+            },
+            Workout {
+                reps,
+                nodes,
+                recovery,
+                ..
+            } => {
+                *reps as f32 * nodes.iter().map(|n| n.calc_known(rtype)).sum::<f32>()
+                    + self.recovery_runs() * recovery.as_ref().map_or(0.0, |r| r.calc_known(rtype))
+            }
+        }
+    }
+
+    // the unknown step and how often it is run, `factor` is the number of runs of `self`
+    fn unknown_mut(&mut self, factor: f32) -> Option<(&mut RunPart, f32)> {
+        let recovery_runs = factor * self.recovery_runs();
+        match self {
+            Step { time, .. } if time.0.is_nan() => Some((self, factor)),
+            Step { .. } => None,
+            Workout {
+                reps,
+                nodes,
+                recovery,
+                ..
+            } => {
+                let runs = factor * *reps as f32;
+                if let Some(found) = nodes.iter_mut().find_map(|n| n.unknown_mut(runs)) {
+                    return Some(found);
+                }
+                recovery.as_mut()?.unknown_mut(recovery_runs)
+            }
+        }
+    }
+
+    /// Pace of a step, "start->end" for progressions and "fast-slow" for pace ranges.
+    /// The heart rate of heart rate only steps, empty for workouts.
+    pub fn pace(&self) -> String {
+        match self {
+            Step {
+                speed,
+                hr: Some(hr),
+                ..
+            } if *speed == Speed(0.0) => format_hr(*hr),
+            Step {
+                speed,
+                end_speed: Some(end_speed),
+                ..
+            } => format!(
+                "{}->{}",
+                get_units().pace(*speed),
+                get_units().pace(*end_speed)
+            ),
+            Step {
+                speed_range: Some((low, high)),
+                ..
+            } => range2pace(*low, *high, get_units()),
+            Step { speed, .. } => get_units().pace(*speed),
+            Workout { .. } => String::new(),
+        }
+    }
+
+    /// Total distance (m) and time (s) per intensity.
+    pub fn calc_intensities(&self) -> BTreeMap<String, (f32, f32)> {
+        let mut totals = BTreeMap::new();
+        self.add_intensities(1.0, &mut totals);
+        totals
+    }
+
+    fn add_intensities(&self, factor: f32, totals: &mut BTreeMap<String, (f32, f32)>) {
+        match self {
+            Step {
+                intensity,
+                distance,
+                time,
+                ..
+            } => {
+                let total = totals.entry(intensity.clone()).or_insert((0.0, 0.0));
+                total.0 += factor * distance;
+                total.1 += factor * time.0;
+            }
+            Workout {
+                reps,
+                nodes,
+                recovery,
+                ..
+            } => {
+                for n in nodes.iter() {
+                    n.add_intensities(factor * *reps as f32, totals);
+                }
+                if let Some(r) = recovery {
+                    r.add_intensities(factor * self.recovery_runs(), totals);
+                }
+            }
+        }
+    }
+
+    // number of recoveries, between the repetitions or between the elements of a ladder
+    fn recovery_runs(&self) -> f32 {
+        match self {
+            Step { .. } => 0.0,
+            Workout {
+                reps,
+                nodes,
+                ladder: true,
+                ..
+            } => (*reps * (nodes.len() as i32 - 1)).max(0) as f32,
+            Workout { reps, .. } => (reps - 1).max(0) as f32,
+        }
+    }
 }
 
 impl fmt::Display for RunPart {
@@ -81,30 +512,54 @@ impl fmt::Display for RunPart {
         match self {
             Step {
                 rtype,
-                distance,
                 speed,
+                hr,
+                distance,
                 time,
-            } => match rtype {
-                RunType::Distance => write!(
-                    f,
-                    "{:.*} km @ {} min/km pace",
-                    1,
-                    distance / 1000.0,
-                    speed2pace(*speed)
-                ),
-                RunType::Time => write!(
-                    f,
-                    "{}:{:02} min @ {} min/km pace",
-                    *time as i32 / 60,
-                    *time as i32 % 60,
-                    speed2pace(*speed)
-                ),
-            },
-            Workout { reps, nodes } => {
-                writeln!(f, "\n{} * (", reps)?;
+                ..
+            } => {
+                match rtype {
+                    RunType::Distance => write!(
+                        f,
+                        "{} {} @ ",
+                        get_units().distance(*distance),
+                        get_units().label(),
+                    )?,
+                    RunType::Time => write!(f, "{} min @ ", time)?,
+                }
+                match hr {
+                    Some(_) if *speed == Speed(0.0) => write!(f, "{}", self.pace()),
+                    Some(hr) => write!(
+                        f,
+                        "{} {} pace, {}",
+                        self.pace(),
+                        get_units().pace_unit().label(),
+                        format_hr(*hr)
+                    ),
+                    None => write!(
+                        f,
+                        "{} {} pace",
+                        self.pace(),
+                        get_units().pace_unit().label()
+                    ),
+                }
+            }
+            Workout {
+                reps,
+                nodes,
+                recovery,
+                ladder,
+            } => {
+                match ladder {
+                    true => writeln!(f, "\nladder(")?,
+                    false => writeln!(f, "\n{} * (", reps)?,
+                }
                 for n in nodes.iter() {
                     writeln!(f, "  {}", n)?;
                 }
+                if let Some(r) = recovery {
+                    writeln!(f, "  w/ {}", r)?;
+                }
                 writeln!(f, ")")
             }
         }
@@ -114,7 +569,6 @@ impl fmt::Display for RunPart {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::utils::pace2speed;
     use approx::assert_abs_diff_eq;
 
     #[test]
@@ -123,12 +577,146 @@ mod tests {
         if let RunPart::Workout { ref mut nodes, .. } = t {
             nodes.push(RunPart::part_from_distance(
                 1000.0,
-                pace2speed("5:00").unwrap(),
+                "5:00".parse::<Speed>().unwrap(),
+                "M",
+            ));
+            nodes.push(RunPart::part_from_time(
+                Duration(240.0),
+                "4:00".parse::<Speed>().unwrap(),
+                "I",
             ));
-            nodes.push(RunPart::part_from_time(240.0, pace2speed("4:00").unwrap()));
             assert_abs_diff_eq!(t.calc_time(), 1080.0);
             assert_abs_diff_eq!(t.calc_distance(), 4000.0);
         }
         // TODO assert_eq!(t.pace(), "4:30");
     }
+
+    #[test]
+    fn json_roundtrip() {
+        let mut t = RunPart::new_workout(2);
+        if let RunPart::Workout { ref mut nodes, .. } = t {
+            nodes.push(RunPart::part_from_distance(1600.0, Speed(4.0), "T"));
+            nodes.push(RunPart::part_from_time(Duration(60.0), Speed(1.25), "rst"));
+        }
+        let json = serde_json::to_string(&t).unwrap();
+        assert_eq!(
+            json,
+            r#"{"type":"workout","reps":2,"nodes":[{"type":"step","rtype":"distance","intensity":"T","speed":4.0,"time":400.0,"distance":1600.0},{"type":"step","rtype":"time","intensity":"rst","speed":1.25,"time":60.0,"distance":75.0}]}"#
+        );
+        assert_eq!(serde_json::from_str::<RunPart>(&json).unwrap(), t);
+    }
+
+    #[test]
+    fn intensity_totals() {
+        let mut t = RunPart::new_workout(1);
+        if let RunPart::Workout { ref mut nodes, .. } = t {
+            nodes.push(RunPart::part_from_distance(2000.0, Speed(4.0), "E"));
+            let mut reps = RunPart::new_workout(3);
+            if let RunPart::Workout { ref mut nodes, .. } = reps {
+                nodes.push(RunPart::part_from_time(Duration(60.0), Speed(5.0), "I"));
+                nodes.push(RunPart::part_from_time(Duration(60.0), Speed(2.0), "E"));
+            }
+            nodes.push(reps);
+        }
+        let totals = t.calc_intensities();
+        assert_eq!(totals.len(), 2);
+        assert_abs_diff_eq!(totals["E"].0, 2360.0);
+        assert_abs_diff_eq!(totals["E"].1, 680.0);
+        assert_abs_diff_eq!(totals["I"].0, 900.0);
+        assert_abs_diff_eq!(totals["I"].1, 180.0);
+    }
+
+    #[test]
+    fn progression() {
+        let d = RunPart::ramp_from_distance(9000.0, Speed(2.5), Speed(3.5), "E->M");
+        assert_abs_diff_eq!(d.calc_time(), 3000.0);
+        let t = RunPart::ramp_from_time(Duration(1200.0), Speed(3.0), Speed(4.0), "M->T");
+        assert_abs_diff_eq!(t.calc_distance(), 4200.0);
+        assert_eq!(t.pace(), "5:33->4:10");
+
+        let json = serde_json::to_string(&t).unwrap();
+        assert!(json.contains(r#""end_speed":4.0"#));
+        assert_eq!(serde_json::from_str::<RunPart>(&json).unwrap(), t);
+    }
+
+    #[test]
+    fn speed_ranges() {
+        let mut t = RunPart::new_workout(3);
+        if let RunPart::Workout { ref mut nodes, .. } = t {
+            nodes.push(
+                RunPart::part_from_distance(2000.0, Speed(4.0), "T")
+                    .with_range((Speed(3.5), Speed(5.0))),
+            );
+            nodes.push(
+                RunPart::part_from_time(Duration(100.0), Speed(2.0), "jg")
+                    .with_range((Speed(1.5), Speed(2.5))),
+            );
+            nodes.push(
+                RunPart::part_from_time(Duration(60.0), Speed(1.0), "rst")
+                    .with_range((Speed(1.0), Speed(1.0))),
+            );
+        }
+        let (fast, slow) = t.time_range();
+        assert_abs_diff_eq!(fast, 3.0 * 560.0);
+        assert_abs_diff_eq!(slow, 3.0 * (2000.0 / 3.5 + 160.0), epsilon = 0.001);
+        assert_eq!(t.distance_range(), (3.0 * 2210.0, 3.0 * 2310.0));
+        if let RunPart::Workout { ref nodes, .. } = t {
+            assert_eq!(nodes[1].pace(), "6:40-11:07");
+            assert_eq!(nodes[2].pace(), "16:40");
+        }
+
+        let json = serde_json::to_string(&t).unwrap();
+        assert!(json.contains(r#""speed_range":[3.5,5.0]"#));
+        assert_eq!(serde_json::from_str::<RunPart>(&json).unwrap(), t);
+    }
+
+    #[test]
+    fn solve_unknown() {
+        let mut t = RunPart::new_workout(1);
+        if let RunPart::Workout { ref mut nodes, .. } = t {
+            nodes.push(RunPart::part_unknown(Speed(4.0), "E"));
+            let mut reps = RunPart::new_workout(2);
+            if let RunPart::Workout { ref mut nodes, .. } = reps {
+                nodes.push(RunPart::part_from_time(Duration(60.0), Speed(5.0), "I"));
+                nodes.push(RunPart::part_unknown(Speed(2.0), "jg"));
+            }
+            nodes.push(reps);
+        }
+        assert_eq!(t.unknowns(), 2);
+        // the first unknown step is solved
+        let s = t.solve(RunType::Time, 1320.0).unwrap();
+        assert_abs_diff_eq!(s.calc_time(), 1200.0);
+        assert_abs_diff_eq!(s.calc_distance(), 4800.0);
+        assert_eq!(t.unknowns(), 1);
+        // 2 runs of the unknown step
+        let s = t.solve(RunType::Distance, 5800.0).unwrap();
+        assert_abs_diff_eq!(s.calc_distance(), 200.0);
+        assert_abs_diff_eq!(t.calc_distance(), 5800.0);
+        assert!(t.solve(RunType::Time, 100.0).is_err());
+    }
+
+    #[test]
+    fn recovery_between_reps() {
+        let mut t = RunPart::new_workout(3);
+        if let RunPart::Workout {
+            ref mut nodes,
+            ref mut recovery,
+            ..
+        } = t
+        {
+            nodes.push(RunPart::part_from_distance(400.0, Speed(5.0), "R"));
+            *recovery = Some(Box::new(RunPart::part_from_time(
+                Duration(90.0),
+                Speed(2.0),
+                "jg",
+            )));
+        }
+        assert_abs_diff_eq!(t.calc_time(), 3.0 * 80.0 + 2.0 * 90.0);
+        assert_abs_diff_eq!(t.calc_distance(), 3.0 * 400.0 + 2.0 * 180.0);
+        assert_abs_diff_eq!(t.calc_intensities()["jg"].1, 180.0);
+
+        let json = serde_json::to_string(&t).unwrap();
+        assert!(json.contains(r#""recovery":{"type":"step""#));
+        assert_eq!(serde_json::from_str::<RunPart>(&json).unwrap(), t);
+    }
 }
@@ -9,5 +9,283 @@ fn cli_workout() {
         .arg("10E + 3 * (2 min I + 2 min rst)");
     cmd.assert()
         .success()
-        .stdout("11.9 km, 1:08 h, 5:45 min/km\n");
+        .stdout("11.9 km, 1:09 h, 5:45 min/km\n");
+}
+
+#[test]
+fn cli_imperial() {
+    let mut cmd = Command::cargo_bin("workout").unwrap();
+    cmd.arg("-c")
+        .arg("tests/paces.toml")
+        .arg("--units")
+        .arg("imperial")
+        .arg("-w")
+        .arg("6E + 3 * 1 T");
+    cmd.assert()
+        .success()
+        .stdout("9.0 mi, 0:47 h, 5:12 min/mi\n");
+}
+
+#[test]
+fn cli_fmt() {
+    let path = std::env::temp_dir().join("workout_cli_fmt.workouts");
+    std::fs::write(&path, "3.2E+2*(1.6T + 1min rst)\n\n10 min  E\n").unwrap();
+
+    let mut cmd = Command::cargo_bin("workout").unwrap();
+    cmd.arg("-c")
+        .arg("tests/paces.toml")
+        .arg("fmt")
+        .arg("--check")
+        .arg(&path);
+    cmd.assert().failure();
+
+    let mut cmd = Command::cargo_bin("workout").unwrap();
+    cmd.arg("-c").arg("tests/paces.toml").arg("fmt").arg(&path);
+    cmd.assert().success();
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        "3.2 E + 2 * (1.6 T + 1 min rst)\n\n10 min E\n"
+    );
+
+    let mut cmd = Command::cargo_bin("workout").unwrap();
+    cmd.arg("-c")
+        .arg("tests/paces.toml")
+        .arg("fmt")
+        .arg("--check")
+        .arg(&path);
+    cmd.assert().success();
+}
+
+#[test]
+fn cli_athletes() {
+    let mut cmd = Command::cargo_bin("workout").unwrap();
+    cmd.arg("-c")
+        .arg("tests/athletes.toml")
+        .arg("-a")
+        .arg("ben")
+        .arg("-w")
+        .arg("10E");
+    cmd.assert()
+        .success()
+        .stdout("10.0 km, 1:00 h, 6:00 min/km\n");
+
+    let mut cmd = Command::cargo_bin("workout").unwrap();
+    cmd.arg("-c")
+        .arg("tests/athletes.toml")
+        .arg("compare")
+        .arg("10E + 3 * (2 min I + 2 min rst)");
+    cmd.assert().success().stdout(
+        "              anna      ben
+distance      11.9 km   11.8 km
+duration      1:09 h    1:12 h
+pace          5:45      6:05
+10 E          5:40      6:00
+3 * (
+  2 min I     3:55      4:10
+  2 min rst   15:00     15:00
+)
+",
+    );
+}
+
+#[test]
+fn cli_group() {
+    let mut cmd = Command::cargo_bin("workout").unwrap();
+    cmd.arg("-c")
+        .arg("tests/athletes.toml")
+        .arg("group")
+        .arg("3 * (1.6 I + 2 min rst) + 4 * (2 min I + 1 min rst)");
+    cmd.assert().success().stdout(
+        "                 anna     ben      spread
+3 * (
+  1.6 I          6:16     6:40     0:24
+    behind       0 m      96 m
+    equal time   1702 m   1600 m
+  2 min rst      133 m    133 m    0 m
+)
+4 * (
+  2 min I        511 m    480 m    31 m
+  1 min rst      67 m     67 m     0 m
+)
+",
+    );
+}
+
+#[test]
+fn cli_macros() {
+    let path = std::env::temp_dir().join("workout_cli_macros.workouts");
+    std::fs::write(
+        &path,
+        "let WU = 3.2E\nlet CRUISE(n, d) = n * (d T + 1min rst)\nWU + CRUISE(4, 1.6) + WU\n10 E\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("workout").unwrap();
+    cmd.arg("-c").arg("tests/paces.toml").arg("-f").arg(&path);
+    cmd.assert()
+        .success()
+        .stdout("13.1 km, 1:07 h, 5:10 min/km\n10.0 km, 0:57 h, 5:40 min/km\n");
+
+    // errors have the same format as for `-w`, with the line in the file
+    let invalid = std::env::temp_dir().join("workout_cli_invalid.workouts");
+    std::fs::write(&invalid, "let WU = 3.2E\n10 E\n10 E + 5 X\n").unwrap();
+    let mut cmd = Command::cargo_bin("workout").unwrap();
+    cmd.arg("-c")
+        .arg("tests/paces.toml")
+        .arg("-f")
+        .arg(&invalid);
+    cmd.assert().success().stdout(
+        "10.0 km, 0:57 h, 5:40 min/km\n\
+         invalid workout: 3:10: expected intensity (CV, E, H, HM, I, M, R, T, jg, jog, rest, rst)\n",
+    );
+
+    // definitions and references are kept by the formatter
+    let mut cmd = Command::cargo_bin("workout").unwrap();
+    cmd.arg("-c")
+        .arg("tests/paces.toml")
+        .arg("fmt")
+        .arg("--check")
+        .arg(&path);
+    cmd.assert().success();
+}
+
+#[test]
+fn cli_scale() {
+    let path = std::env::temp_dir().join("workout_cli_scale.workouts");
+    std::fs::write(&path, "let WU = 2E\nWU + 5 * (1 I)\n\n10 E\n").unwrap();
+
+    let mut cmd = Command::cargo_bin("workout").unwrap();
+    cmd.arg("-c")
+        .arg("tests/paces.toml")
+        .arg("scale")
+        .arg(&path)
+        .arg("--percent")
+        .arg("50");
+    cmd.assert()
+        .success()
+        .stdout("let WU = 2E\n1 E + 3 * (1 I)\n\n5 E\n");
+
+    let mut cmd = Command::cargo_bin("workout").unwrap();
+    cmd.arg("-c")
+        .arg("tests/paces.toml")
+        .arg("scale")
+        .arg(&path)
+        .arg("--distance")
+        .arg("4")
+        .arg("--only")
+        .arg("easy");
+    cmd.assert().failure();
+
+    for arg in ["--percent=-50", "--percent=0", "--distance=0"] {
+        let mut cmd = Command::cargo_bin("workout").unwrap();
+        cmd.arg("-c")
+            .arg("tests/paces.toml")
+            .arg("scale")
+            .arg(&path)
+            .arg(arg);
+        cmd.assert().failure();
+    }
+}
+
+#[test]
+fn cli_treadmill() {
+    let mut cmd = Command::cargo_bin("workout").unwrap();
+    cmd.arg("-c")
+        .arg("tests/paces.toml")
+        .arg("treadmill")
+        .arg("2 E + 3 * (1 I + 2 min rst)")
+        .arg("--incline");
+    cmd.assert().success().stdout(
+        "              speed       incline   time
+2 E           10.6 km/h   1%        11:19
+3 * (
+  1 I         15.3 km/h   1%        3:55
+  2 min rst   4.0 km/h    1%        2:00
+)
+",
+    );
+
+    let mut cmd = Command::cargo_bin("workout").unwrap();
+    cmd.arg("-c")
+        .arg("tests/paces.toml")
+        .arg("treadmill")
+        .arg("2 X");
+    cmd.assert().failure();
+
+    for increment in ["0", "-0.5"] {
+        let mut cmd = Command::cargo_bin("workout").unwrap();
+        cmd.arg("-c")
+            .arg("tests/paces.toml")
+            .arg("treadmill")
+            .arg("2 E")
+            .arg(format!("--increment={}", increment));
+        cmd.assert().failure();
+    }
+}
+
+#[test]
+fn cli_track() {
+    let mut cmd = Command::cargo_bin("workout").unwrap();
+    cmd.arg("-c")
+        .arg("tests/paces.toml")
+        .arg("track")
+        .arg("4 * (800 I + 2 min rst)");
+    cmd.assert().success().stdout(
+        "              laps   400 m   200 m
+4 * (
+  800 I       2      1:34    0:47
+  2 min rst
+)
+",
+    );
+
+    let mut cmd = Command::cargo_bin("workout").unwrap();
+    cmd.arg("-c")
+        .arg("tests/paces.toml")
+        .arg("track")
+        .arg("800 I")
+        .arg("--lane")
+        .arg("0");
+    cmd.assert().failure();
+
+    for length in ["0", "-0.5", "inf"] {
+        let mut cmd = Command::cargo_bin("workout").unwrap();
+        cmd.arg("-c")
+            .arg("tests/paces.toml")
+            .arg("track")
+            .arg("800 I")
+            .arg("--length")
+            .arg(length);
+        cmd.assert().failure();
+    }
+}
+
+#[test]
+fn cli_heart_rate() {
+    let path = std::env::temp_dir().join("workout_cli_heart_rate.toml");
+    std::fs::write(
+        &path,
+        "max_hr = 190\n[paces]\nE = \"5:40 @ 65-75% max\"\nZ2 = \"130-145 bpm\"\nI = \"3:55\"\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("workout").unwrap();
+    cmd.arg("-c")
+        .arg(&path)
+        .arg("-w")
+        .arg("2 E + 40 min Z2 + 4 * (1 I)");
+    cmd.assert()
+        .success()
+        .stdout("6.0 km + 0:40 h by HR, 1:07 h, 4:30 min/km\n");
+
+    let mut cmd = Command::cargo_bin("workout").unwrap();
+    cmd.arg("-c").arg(&path).arg("-w").arg("5 Z2");
+    cmd.assert()
+        .stdout("invalid workout: 1:3: expected intensity with a pace\n");
+
+    let mut cmd = Command::cargo_bin("workout").unwrap();
+    cmd.arg("-c").arg(&path).arg("treadmill").arg("40 min Z2");
+    cmd.assert()
+        .success()
+        .stdout("            speed         time\n40 min Z2   130-145 bpm   40:00\n");
 }